@@ -0,0 +1,209 @@
+//! Audio settings and sound effects. Everything is played in a separate channel for each
+//! category of sound, so that each category's volume can be set on its own.
+//!
+//! Sound effects are played in response to `GameplayEvent`s, using the sounds listed in
+//! `sounds.ron`.
+
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use bevy_kira_audio::{Audio, AudioChannel};
+use serde::{Deserialize, Serialize};
+
+use crate::{save, AudioHandles, TextureHandles};
+
+const SAVE_KEY: &str = "audio_settings";
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(save::load::<AudioSettings>(SAVE_KEY).unwrap_or_default())
+            .add_event::<GameplayEvent>()
+            .add_system(apply_settings.system().label("apply_audio_settings"))
+            .add_system(
+                play_sfx
+                    .system()
+                    .label("play_sfx")
+                    .after("apply_audio_settings"),
+            );
+    }
+}
+
+/// The volume levels that can be changed by `Action::ChangeVolume`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum VolumeLevel {
+    Master,
+    Sfx,
+    Music,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub mute: bool,
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            mute: false,
+            master: 1.0,
+            sfx: 1.0,
+            music: 0.5,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// Changes a volume level by `amount`, keeping it between 0 and 1.
+    pub fn change_volume(&mut self, level: VolumeLevel, amount: f32) {
+        let volume = match level {
+            VolumeLevel::Master => &mut self.master,
+            VolumeLevel::Sfx => &mut self.sfx,
+            VolumeLevel::Music => &mut self.music,
+        };
+
+        // Round to avoid drifting away from nice numbers after many small steps.
+        *volume = ((*volume + amount) * 100.0).round().clamp(0.0, 100.0) / 100.0;
+
+        info!("{:?} volume is now {}", level, volume);
+    }
+
+    /// The volume that sound effects actually play at.
+    pub fn sfx_volume(&self) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            self.master * self.sfx
+        }
+    }
+
+    /// The volume that music actually plays at.
+    pub fn music_volume(&self) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            self.master * self.music
+        }
+    }
+}
+
+/// Things that happen in the game that might make a sound.
+#[derive(Clone, Copy, Debug, Deserialize, Hash, PartialEq, Eq)]
+pub enum GameplayEvent {
+    /// A character was typed that doesn't match anything.
+    WrongCharacter,
+    /// The typing buffer was submitted without matching anything.
+    TargetRejected,
+    /// The typing buffer was submitted and matched a target.
+    TargetCompleted,
+    /// Every enemy in a wave has been defeated.
+    WaveCleared,
+    /// Something that couldn't be afforded now can be.
+    BecameAffordable,
+    /// An enemy attacked the goal.
+    GoalDamaged,
+    TowerFired,
+    EnemyHit,
+}
+
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "8d1f4a27-52c6-4b0e-b3a9-0e6f7c2d5a14"]
+pub struct SoundEffects {
+    pub sounds: HashMap<GameplayEvent, SoundEffect>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SoundEffect {
+    pub file: String,
+    /// Multiplied with the sfx volume.
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    /// The shortest time in seconds between two plays of this sound. Events that come in
+    /// quicker than this are ignored, so that a pile of towers firing at once doesn't get
+    /// too loud.
+    #[serde(default)]
+    pub min_interval: f32,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+/// Each sound effect gets its own channel so that it can have its own volume, because
+/// bevy_kira_audio only sets volume for a whole channel rather than for a single sound. The
+/// effects volume setting is applied to every one of these channels. Music has its own
+/// channels over in `music`.
+fn sfx_channel(event: GameplayEvent) -> AudioChannel {
+    AudioChannel::new(format!("sfx_{:?}", event))
+}
+
+fn apply_settings(
+    settings: Res<AudioSettings>,
+    audio: Res<Audio>,
+    texture_handles: Res<TextureHandles>,
+    sound_effects_assets: Res<Assets<SoundEffects>>,
+    mut sound_effects_events: EventReader<AssetEvent<SoundEffects>>,
+) {
+    let sound_effects_changed = sound_effects_events.iter().count() > 0;
+
+    if !settings.is_changed() && !sound_effects_changed {
+        return;
+    }
+
+    if let Some(sound_effects) = sound_effects_assets.get(&texture_handles.sound_effects) {
+        for (event, effect) in sound_effects.sounds.iter() {
+            audio
+                .set_volume_in_channel(settings.sfx_volume() * effect.volume, &sfx_channel(*event));
+        }
+    }
+
+    if settings.is_changed() {
+        save::save(SAVE_KEY, &*settings);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_sfx(
+    mut events: EventReader<GameplayEvent>,
+    settings: Res<AudioSettings>,
+    audio: Res<Audio>,
+    audio_handles: Res<AudioHandles>,
+    texture_handles: Res<TextureHandles>,
+    sound_effects_assets: Res<Assets<SoundEffects>>,
+    time: Res<Time>,
+    mut last_played: Local<HashMap<GameplayEvent, f64>>,
+) {
+    let sound_effects = match sound_effects_assets.get(&texture_handles.sound_effects) {
+        Some(sound_effects) => sound_effects,
+        None => return,
+    };
+
+    let now = time.seconds_since_startup();
+
+    for event in events.iter() {
+        if settings.mute {
+            continue;
+        }
+
+        let (effect, handle) = match (
+            sound_effects.sounds.get(event),
+            audio_handles.sounds.get(event),
+        ) {
+            (Some(effect), Some(handle)) => (effect, handle),
+            _ => continue,
+        };
+
+        if let Some(last) = last_played.get(event) {
+            if now - last < effect.min_interval as f64 {
+                continue;
+            }
+        }
+
+        last_played.insert(*event, now);
+
+        audio.play_in_channel(handle.clone(), &sfx_channel(*event));
+    }
+}
@@ -0,0 +1,128 @@
+//! Checks the game data and every map listed in it without opening a window, so that
+//! mistakes can be caught without running the game.
+//!
+//! ```text
+//! cargo make lint-maps
+//! ```
+//!
+//! `.cargo/config` builds for wasm by default, so running it without cargo-make needs the
+//! host's target triple, as in `cargo run --target x86_64-unknown-linux-gnu --bin taipo-lint
+//! -- [path to assets]`.
+//!
+//! Exits with a non-zero status if there were any problems.
+
+use std::{fs, path::Path, process};
+
+use bevy_tiled_prototype::tiled;
+use taipo::{
+    bindings::Bindings,
+    data::{parse_game_data, GameData, MapData},
+    map_validation::{unambiguous_words, validate_map},
+    ACTION_PANEL_ITEMS,
+};
+
+fn main() {
+    let assets = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets".to_string());
+    let assets = Path::new(&assets);
+
+    let game_data_path = assets.join("data/game.ron");
+
+    let game_data = match fs::read(&game_data_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| parse_game_data(&bytes))
+    {
+        Ok(game_data) => game_data,
+        Err(e) => {
+            eprintln!("{}: {}", game_data_path.display(), e);
+            process::exit(1);
+        }
+    };
+
+    // Duplicates and such don't keep a word list from being used, so they're only warnings.
+    for diagnostic in game_data.diagnostics.iter() {
+        println!("{}: warning: {}", game_data_path.display(), diagnostic);
+    }
+
+    let bindings_path = assets.join("data/bindings.ron");
+
+    let bindings: Bindings = match fs::read(&bindings_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| ron::de::from_bytes(&bytes).map_err(anyhow::Error::from))
+    {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("{}: {}", bindings_path.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let problems: usize = game_data
+        .maps
+        .iter()
+        .map(|map_data| lint_map(assets, map_data, &game_data, &bindings))
+        .sum();
+
+    if problems > 0 {
+        eprintln!("{} problem(s) found", problems);
+        process::exit(1);
+    }
+
+    println!("{} map(s) ok", game_data.maps.len());
+}
+
+/// Prints the problems with a map and returns how many there were.
+fn lint_map(assets: &Path, map_data: &MapData, game_data: &GameData, bindings: &Bindings) -> usize {
+    let path = assets.join(&map_data.file);
+
+    let map = match tiled::parse_file(&path) {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    let mut problems: Vec<String> = validate_map(&map, game_data)
+        .iter()
+        .map(|problem| problem.to_string())
+        .collect();
+
+    // Every tower slot and action panel item needs a word of its own.
+    let tower_slots = map
+        .object_groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == "tower_slot")
+        .count();
+    let needed = tower_slots + ACTION_PANEL_ITEMS;
+
+    // Maps without recommended word lists may be played with any of them.
+    let selections: Vec<Vec<String>> = if map_data.word_lists.is_empty() {
+        let mut lists: Vec<String> = game_data.word_lists.keys().cloned().collect();
+        lists.sort();
+        lists.into_iter().map(|list| vec![list]).collect()
+    } else {
+        vec![map_data.word_lists.clone()]
+    };
+
+    for lists in selections.iter() {
+        let available = unambiguous_words(game_data, bindings, lists);
+
+        if available < needed {
+            problems.push(format!(
+                "Word lists \"{}\" have {} words that can be told apart, but {} are needed",
+                lists.join("\", \""),
+                available,
+                needed
+            ));
+        }
+    }
+
+    for problem in problems.iter() {
+        eprintln!("{}: {}", path.display(), problem);
+    }
+
+    problems.len()
+}
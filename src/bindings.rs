@@ -0,0 +1,163 @@
+//! Key bindings and the command words that can be typed to do things like toggling mute,
+//! loaded from `bindings.ron`.
+
+use crate::{data, Action, TypingTarget};
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "3e5b0c1a-7d2f-4f0e-9a43-6c1b8f2d9e57"]
+pub struct Bindings {
+    /// Keys that trigger actions. Actions that edit the typing buffer, like `Submit`, can
+    /// only be bound to keys.
+    pub keys: Vec<KeyBinding>,
+    /// Words that can be typed and submitted to trigger actions.
+    pub commands: HashMap<String, Action>,
+    /// Replacement command words for each word list, like `"help": "たすけて"`.
+    #[serde(default)]
+    pub translations: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyBinding {
+    pub key: Key,
+    /// Whether control needs to be held down.
+    #[serde(default)]
+    pub ctrl: bool,
+    /// Whether this binding only applies when the typing buffer is empty. These win over
+    /// other bindings for the same key.
+    #[serde(default)]
+    pub empty: bool,
+    pub action: Action,
+}
+
+/// The keys that can be bound. Keys that type characters are left out because they're
+/// needed for typing.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum Key {
+    Return,
+    Back,
+    Delete,
+    Escape,
+    Tab,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+}
+
+impl From<Key> for KeyCode {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Return => KeyCode::Return,
+            Key::Back => KeyCode::Back,
+            Key::Delete => KeyCode::Delete,
+            Key::Escape => KeyCode::Escape,
+            Key::Tab => KeyCode::Tab,
+            Key::Insert => KeyCode::Insert,
+            Key::Home => KeyCode::Home,
+            Key::End => KeyCode::End,
+            Key::PageUp => KeyCode::PageUp,
+            Key::PageDown => KeyCode::PageDown,
+            Key::F1 => KeyCode::F1,
+            Key::F2 => KeyCode::F2,
+            Key::F3 => KeyCode::F3,
+            Key::F4 => KeyCode::F4,
+            Key::F5 => KeyCode::F5,
+            Key::F6 => KeyCode::F6,
+            Key::F7 => KeyCode::F7,
+            Key::F8 => KeyCode::F8,
+            Key::F9 => KeyCode::F9,
+            Key::F10 => KeyCode::F10,
+            Key::F11 => KeyCode::F11,
+            Key::F12 => KeyCode::F12,
+        }
+    }
+}
+
+/// Sent when a bound key is pressed for an action that isn't handled by the typing buffer.
+pub struct ActionEvent(pub Action);
+
+impl Bindings {
+    /// Returns the action bound to `key`, if any.
+    pub fn key_action(&self, key: KeyCode, ctrl: bool, buffer_empty: bool) -> Option<&Action> {
+        let mut matching = self.keys.iter().filter(|binding| {
+            KeyCode::from(binding.key) == key
+                && binding.ctrl == ctrl
+                && (buffer_empty || !binding.empty)
+        });
+
+        matching
+            .clone()
+            .find(|binding| binding.empty)
+            .or_else(|| matching.next())
+            .map(|binding| &binding.action)
+    }
+
+    /// Returns the command words to use with the given word lists, along with their
+    /// actions. When more than one of the lists translates a word, the first one wins.
+    ///
+    /// They're sorted by word, so that they're spawned in the same order every time.
+    pub fn commands(&self, word_lists: &[String]) -> Vec<(String, Action)> {
+        let mut commands: Vec<(String, Action)> = self
+            .commands
+            .iter()
+            .map(|(word, action)| {
+                let translated = word_lists
+                    .iter()
+                    .filter_map(|list| self.translations.get(list))
+                    .find_map(|translations| translations.get(word))
+                    .unwrap_or(word);
+
+                (translated.clone(), action.clone())
+            })
+            .collect();
+
+        commands.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        commands
+    }
+}
+
+/// Returns a fixed typing target for a command word. Readings for kana are filled in
+/// automatically, and anything else is typed as written.
+pub fn command_target(word: &str) -> TypingTarget {
+    let parsed = if word.is_ascii() {
+        data::parse_uniform_chars(word)
+    } else {
+        let (parsed, problems) = data::parse_parenthesized(word);
+        if !problems.is_empty() {
+            warn!(
+                "Command word \"{}\" needs readings for everything but kana",
+                word
+            );
+        }
+        parsed
+    };
+
+    let mut target = match parsed.into_iter().next() {
+        Some((_, _, target)) => target,
+        None => TypingTarget {
+            render: word.chars().map(|c| c.to_string()).collect(),
+            ascii: word.chars().map(|c| vec![c.to_string()]).collect(),
+            ..Default::default()
+        },
+    };
+
+    target.fixed = true;
+
+    target
+}
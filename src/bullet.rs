@@ -1,11 +1,17 @@
-use crate::{layer, Armor, HitPoints, StatusEffect, StatusEffects};
+use crate::{
+    audio::GameplayEvent, layer, Armor, GameMarker, HitPoints, StatusEffect, StatusEffects,
+    TaipoState,
+};
 use bevy::prelude::*;
 
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(update.system().before("enemy_death"));
+        app.add_system_set(
+            SystemSet::on_update(TaipoState::Ready)
+                .with_system(update.system().before("enemy_death")),
+        );
     }
 }
 
@@ -33,6 +39,7 @@ pub fn spawn(
             transform: Transform::from_translation(position),
             ..Default::default()
         })
+        .insert(GameMarker)
         .insert(Bullet {
             target,
             damage,
@@ -54,6 +61,7 @@ fn update(
         ),
         Without<Bullet>,
     >,
+    mut gameplay_events: EventWriter<GameplayEvent>,
 ) {
     for (entity, mut transform, mut bullet) in query.iter_mut() {
         if let Ok((target_transform, mut hp, target_armor, target_status)) =
@@ -90,6 +98,8 @@ fn update(
 
                 hp.current = hp.current.saturating_sub(damage);
 
+                gameplay_events.send(GameplayEvent::EnemyHit);
+
                 commands.entity(entity).despawn_recursive();
             }
         } else {
@@ -1,4 +1,7 @@
-use crate::TypingTarget;
+use crate::{
+    audio::SoundEffects, bindings::Bindings, endless::EndlessData, enemy::EnemyKind, kana,
+    music::MusicData, typing::MAX_INPUTS, TowerType, TypingTarget,
+};
 use bevy::utils::HashMap;
 use bevy::{
     asset::{AssetLoader, LoadContext, LoadedAsset},
@@ -9,28 +12,58 @@ use bevy::{
 use bevy_asset_ron::*;
 use nom::{
     bytes::complete::is_not,
-    character::complete::{char, line_ending, space0},
-    multi::{fold_many0, separated_list0},
+    character::complete::char,
     sequence::{delimited, pair},
     IResult,
 };
 use serde::Deserialize;
+use std::fmt;
 
-// Tower stats, prices, etc should go in here eventually
 #[serde(rename = "GameData")]
 #[derive(Debug, Deserialize)]
 pub struct RawGameData {
     pub word_lists: HashMap<String, WordList>,
+    pub towers: HashMap<TowerType, TowerData>,
+    pub enemies: HashMap<EnemyKind, EnemyData>,
+    #[serde(default)]
+    pub music: MusicData,
+    pub maps: Vec<MapData>,
+    #[serde(default)]
+    pub endless: EndlessData,
+}
+
+/// A map that can be picked from the main menu.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MapData {
+    pub name: String,
+    /// Path to the Tiled map.
+    pub file: String,
+    /// How hard the map is, from 1 up.
+    pub difficulty: u32,
+    /// Word lists that go well with the map. These are only suggestions, so any word lists
+    /// can be played with any map.
+    #[serde(default)]
+    pub word_lists: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct WordList {
     kind: WordListKind,
     string: String,
+    /// The characters that can be typed while playing with this list, including any
+    /// needed for commands like `help`. Anything can be typed when this is left out.
+    /// Uppercase letters that aren't accepted are typed as lowercase.
+    #[serde(default)]
+    accepted_chars: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub enum WordListKind {
+    /// Lines like `ひだり手(te)`, where each reading in parentheses applies to the text in
+    /// front of it. Readings may be left out for hiragana and katakana. If the text in front
+    /// of a reading starts with kana and then has something else, like `ひだり手`, only
+    /// the non-kana part uses the reading. A reading may list alternatives, like
+    /// `四(yon|yo)`, any of which are accepted.
     Parenthesized,
     UniformChars,
 }
@@ -39,6 +72,65 @@ pub enum WordListKind {
 #[uuid = "fa116b6c-6c13-11eb-9439-0242ac130002"]
 pub struct GameData {
     pub word_lists: HashMap<String, Vec<TypingTarget>>,
+    /// The characters accepted by each word list that restricts them.
+    pub accepted_chars: HashMap<String, String>,
+    pub towers: HashMap<TowerType, TowerData>,
+    pub enemies: HashMap<EnemyKind, EnemyData>,
+    pub music: MusicData,
+    pub maps: Vec<MapData>,
+    pub endless: EndlessData,
+    /// Problems with the word lists that were not serious enough to prevent loading.
+    pub diagnostics: Vec<WordListDiagnostic>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TowerData {
+    pub price: u32,
+    /// Stats for each level of the tower, starting at level 1. The number of entries is
+    /// the tower's max level.
+    pub levels: Vec<TowerLevel>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TowerLevel {
+    pub range: f32,
+    pub damage: u32,
+    pub speed: f32,
+    /// The price of upgrading from this level to the next one. Ignored for the last level.
+    #[serde(default)]
+    pub upgrade_price: u32,
+}
+
+/// An enemy archetype. Map waves refer to these by name and may override any of the
+/// numeric stats.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemyData {
+    pub texture: String,
+    pub animation: String,
+    pub hp: u32,
+    pub armor: u32,
+    pub speed: f32,
+    /// Currency awarded when this enemy dies.
+    pub bounty: u32,
+    /// Goal hit points removed by each attack.
+    pub damage: u32,
+    /// Seconds between attacks once the enemy reaches the goal.
+    pub attack_interval: f32,
+}
+
+impl TowerData {
+    pub fn max_level(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Returns the stats for a (1-indexed) tower level.
+    pub fn level(&self, level: u32) -> Option<&TowerLevel> {
+        if level == 0 {
+            return None;
+        }
+
+        self.levels.get(level as usize - 1)
+    }
 }
 
 #[derive(Debug, Deserialize, TypeUuid)]
@@ -65,9 +157,112 @@ impl Plugin for GameDataPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_asset::<GameData>()
             .init_asset_loader::<GameDataLoader>()
-            .add_plugin(RonAssetPlugin::<AnimationData>::new(&["anim.ron"]));
+            .add_plugin(RonAssetPlugin::<AnimationData>::new(&["anim.ron"]))
+            .add_plugin(RonAssetPlugin::<Bindings>::new(&["bindings.ron"]))
+            .add_plugin(RonAssetPlugin::<SoundEffects>::new(&["sounds.ron"]));
+    }
+}
+/// A problem found in a word list. Line numbers are relative to the start of the word list's
+/// string and columns are counted in characters, both starting at 1.
+#[derive(Clone, Debug)]
+pub struct WordListDiagnostic {
+    pub list: String,
+    pub line: usize,
+    pub column: usize,
+    pub problem: WordListProblem,
+}
+
+impl fmt::Display for WordListDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "word list \"{}\", line {}, column {}: {}",
+            self.list, self.line, self.column, self.problem
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WordListProblem {
+    UnclosedParen,
+    UnexpectedCloseParen,
+    EmptyReading,
+    /// A reading with no text in front of it, like `(ka)`.
+    MissingText,
+    /// Text at the end of a line with no reading after it.
+    MissingReading(String),
+    /// Kana that we don't know how to read.
+    UnreadableKana(String),
+    /// An entry identical to the one on an earlier line.
+    Duplicate {
+        line: usize,
+    },
+    /// An entry that can be typed the same way as the one on an earlier line.
+    Ambiguous {
+        line: usize,
+    },
+    /// An entry that can be typed more than `MAX_INPUTS` ways. It's left out of the list.
+    TooManyInputs {
+        count: usize,
+    },
+}
+
+impl WordListProblem {
+    /// Whether this problem prevents the word list from being used at all.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            WordListProblem::Duplicate { .. }
+                | WordListProblem::Ambiguous { .. }
+                | WordListProblem::TooManyInputs { .. }
+        )
+    }
+}
+
+impl fmt::Display for WordListProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordListProblem::UnclosedParen => write!(f, "unclosed parenthesis"),
+            WordListProblem::UnexpectedCloseParen => write!(f, "unexpected closing parenthesis"),
+            WordListProblem::EmptyReading => write!(f, "empty reading"),
+            WordListProblem::MissingText => write!(f, "reading without any text before it"),
+            WordListProblem::MissingReading(text) => {
+                write!(f, "text \"{}\" without a reading", text)
+            }
+            WordListProblem::UnreadableKana(text) => {
+                write!(f, "no reading known for kana \"{}\"", text)
+            }
+            WordListProblem::Duplicate { line } => write!(f, "duplicate of line {}", line),
+            WordListProblem::Ambiguous { line } => {
+                write!(f, "typed the same way as line {}", line)
+            }
+            WordListProblem::TooManyInputs { count } => write!(
+                f,
+                "can be typed {} ways, more than the {} allowed, so it was left out",
+                count, MAX_INPUTS
+            ),
+        }
     }
 }
+
+/// Returned by `GameDataLoader` when any of the word lists have errors in them.
+#[derive(Debug)]
+pub struct GameDataError {
+    pub diagnostics: Vec<WordListDiagnostic>,
+}
+
+impl fmt::Display for GameDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} problem(s) in word lists", self.diagnostics.len())?;
+        for diagnostic in self.diagnostics.iter() {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GameDataError {}
+
 #[derive(Default)]
 pub struct GameDataLoader;
 
@@ -78,17 +273,10 @@ impl AssetLoader for GameDataLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let raw_game_data = ron::de::from_bytes::<RawGameData>(bytes)?;
-
-            let mut game_data = GameData::default();
+            let game_data = parse_game_data(bytes)?;
 
-            for (key, word_list) in raw_game_data.word_lists.iter() {
-                let targets = match word_list.kind {
-                    WordListKind::Parenthesized => parse_parenthesized(&word_list.string)?,
-                    WordListKind::UniformChars => parse_uniform_chars(&word_list.string)?,
-                };
-
-                game_data.word_lists.insert(key.clone(), targets);
+            for diagnostic in game_data.diagnostics.iter() {
+                warn!("{}", diagnostic);
             }
 
             load_context.set_default_asset(LoadedAsset::new(game_data));
@@ -102,52 +290,340 @@ impl AssetLoader for GameDataLoader {
     }
 }
 
-pub fn parse_uniform_chars(input: &str) -> Result<Vec<TypingTarget>, anyhow::Error> {
-    Ok(input
-        .lines()
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
-        .map(|l| {
+/// Parses and checks `game.ron`. Word list problems that aren't serious enough to fail are
+/// left in `GameData::diagnostics`.
+pub fn parse_game_data(bytes: &[u8]) -> Result<GameData, anyhow::Error> {
+    let raw_game_data = ron::de::from_bytes::<RawGameData>(bytes)?;
+
+    let mut game_data = GameData::default();
+
+    for (key, word_list) in raw_game_data.word_lists.iter() {
+        let (targets, diagnostics) = parse_word_list(key, word_list);
+
+        game_data.word_lists.insert(key.clone(), targets);
+        game_data.diagnostics.extend(diagnostics);
+
+        if let Some(accepted_chars) = &word_list.accepted_chars {
+            game_data
+                .accepted_chars
+                .insert(key.clone(), accepted_chars.clone());
+        }
+    }
+
+    if game_data.diagnostics.iter().any(|d| d.problem.is_error()) {
+        return Err(GameDataError {
+            diagnostics: game_data.diagnostics,
+        }
+        .into());
+    }
+
+    for tower_type in &[TowerType::Basic, TowerType::Support, TowerType::Debuff] {
+        let tower = raw_game_data
+            .towers
+            .get(tower_type)
+            .ok_or_else(|| anyhow!("No tower data for {:?}", tower_type))?;
+
+        if tower.levels.is_empty() {
+            return Err(anyhow!("No levels in tower data for {:?}", tower_type));
+        }
+
+        if tower.levels.iter().any(|l| l.speed <= 0.0) {
+            return Err(anyhow!("Tower speed must be positive for {:?}", tower_type));
+        }
+    }
+
+    game_data.towers = raw_game_data.towers;
+
+    if raw_game_data.enemies.is_empty() {
+        return Err(anyhow!("No enemies in game data"));
+    }
+
+    for (kind, enemy) in raw_game_data.enemies.iter() {
+        if enemy.attack_interval <= 0.0 {
+            return Err(anyhow!(
+                "Enemy attack interval must be positive for {}",
+                kind.0
+            ));
+        }
+    }
+
+    game_data.enemies = raw_game_data.enemies;
+    game_data.music = raw_game_data.music;
+
+    if raw_game_data.maps.is_empty() {
+        return Err(anyhow!("No maps in game data"));
+    }
+
+    for map in raw_game_data.maps.iter() {
+        if let Some(list) = map
+            .word_lists
+            .iter()
+            .find(|list| !game_data.word_lists.contains_key(*list))
+        {
+            return Err(anyhow!(
+                "Unknown word list \"{}\" recommended for map {}",
+                list,
+                map.name
+            ));
+        }
+    }
+
+    game_data.maps = raw_game_data.maps;
+
+    let endless = &raw_game_data.endless;
+
+    if endless.delay < 0.0 {
+        return Err(anyhow!("Endless wave delay must not be negative"));
+    }
+
+    if endless.interval <= 0.0 {
+        return Err(anyhow!("Endless enemy interval must be positive"));
+    }
+
+    if endless.num < 0.0 || endless.num_per_wave < 0.0 || endless.armor_per_wave < 0.0 {
+        return Err(anyhow!(
+            "Endless enemy numbers and armor must not be negative"
+        ));
+    }
+
+    if endless.hp_growth <= 0.0 || endless.speed_growth <= 0.0 || endless.max_speed <= 0.0 {
+        return Err(anyhow!("Endless growth and max speed must be positive"));
+    }
+
+    game_data.endless = raw_game_data.endless;
+
+    Ok(game_data)
+}
+
+/// Parses a word list, returning the successfully parsed targets along with any problems
+/// that were found.
+pub fn parse_word_list(
+    key: &str,
+    word_list: &WordList,
+) -> (Vec<TypingTarget>, Vec<WordListDiagnostic>) {
+    let mut diagnostics = vec![];
+
+    let parsed = match word_list.kind {
+        WordListKind::Parenthesized => {
+            let (parsed, problems) = parse_parenthesized(&word_list.string);
+
+            diagnostics.extend(problems.into_iter().map(|(line, column, problem)| {
+                WordListDiagnostic {
+                    list: key.to_string(),
+                    line,
+                    column,
+                    problem,
+                }
+            }));
+
+            parsed
+        }
+        WordListKind::UniformChars => parse_uniform_chars(&word_list.string),
+    };
+
+    let mut targets = vec![];
+    let mut seen: Vec<(usize, Vec<String>, Vec<String>)> = vec![];
+
+    for (line, column, target) in parsed.into_iter() {
+        let count = target.input_count();
+        if count > MAX_INPUTS {
+            diagnostics.push(WordListDiagnostic {
+                list: key.to_string(),
+                line,
+                column,
+                problem: WordListProblem::TooManyInputs { count },
+            });
+            continue;
+        }
+
+        let inputs = target.inputs();
+
+        let problem = seen
+            .iter()
+            .find_map(|(seen_line, seen_render, seen_inputs)| {
+                if !inputs.iter().any(|input| seen_inputs.contains(input)) {
+                    None
+                } else if *seen_render == target.render {
+                    Some(WordListProblem::Duplicate { line: *seen_line })
+                } else {
+                    Some(WordListProblem::Ambiguous { line: *seen_line })
+                }
+            });
+
+        if let Some(problem) = problem {
+            diagnostics.push(WordListDiagnostic {
+                list: key.to_string(),
+                line,
+                column,
+                problem,
+            });
+        }
+
+        seen.push((line, target.render.clone(), inputs));
+        targets.push(target);
+    }
+
+    (targets, diagnostics)
+}
+
+/// Returns the trimmed, non-empty lines of `input` along with their line and column numbers.
+fn numbered_lines(input: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    input.lines().enumerate().filter_map(|(i, l)| {
+        let trimmed = l.trim();
+        if trimmed.is_empty() {
+            return None;
+        }
+
+        let indent = l.len() - l.trim_start().len();
+
+        Some((i + 1, l[..indent].chars().count() + 1, trimmed))
+    })
+}
+
+pub fn parse_uniform_chars(input: &str) -> Vec<(usize, usize, TypingTarget)> {
+    numbered_lines(input)
+        .map(|(line, column, l)| {
             let chars = l.chars().map(|c| c.to_string()).collect::<Vec<_>>();
-            TypingTarget {
-                render: chars.clone(),
-                ascii: chars,
-                fixed: false,
-                disabled: false,
-            }
+            (
+                line,
+                column,
+                TypingTarget {
+                    ascii: chars.iter().map(|c| vec![c.clone()]).collect(),
+                    render: chars,
+                    ..Default::default()
+                },
+            )
         })
-        .collect::<Vec<_>>())
+        .collect::<Vec<_>>()
 }
 
-// I attempted to use map_err to get some sort of useful error out of this thing,
-// but then Rust demanded that input be 'static and I gave up.
-pub fn parse_parenthesized(input: &str) -> Result<Vec<TypingTarget>, anyhow::Error> {
-    if let Ok((_, targets)) = separated_list0(line_ending, delimited(space0, line, space0))(input) {
-        Ok(targets
-            .iter()
-            .cloned()
-            .filter(|i| !i.render.is_empty() && !i.ascii.is_empty())
-            .collect())
-    } else {
-        Err(anyhow!("Frustratingly Generic Parser Error"))
-    }
-}
-
-fn line(input: &str) -> IResult<&str, TypingTarget> {
-    fold_many0(
-        render_ascii_pair,
-        TypingTarget {
-            render: vec![],
-            ascii: vec![],
-            fixed: false,
-            disabled: false,
-        },
-        |mut t, item| {
-            t.render.push(item.0.to_string());
-            t.ascii.push(item.1.to_string());
-            t
-        },
-    )(input)
+/// Parses lines like `ひだり手(te)`, returning the targets that parsed
+/// successfully along with the line, column and problem for each line that did not.
+#[allow(clippy::type_complexity)]
+pub fn parse_parenthesized(
+    input: &str,
+) -> (
+    Vec<(usize, usize, TypingTarget)>,
+    Vec<(usize, usize, WordListProblem)>,
+) {
+    let mut targets = vec![];
+    let mut problems = vec![];
+
+    for (line_num, column, l) in numbered_lines(input) {
+        match line(l) {
+            Ok(target) => targets.push((line_num, column, target)),
+            Err((offset, problem)) => {
+                problems.push((line_num, column + l[..offset].chars().count(), problem))
+            }
+        }
+    }
+
+    (targets, problems)
+}
+
+/// Parses a single trimmed line, returning the byte offset of the problem on failure.
+fn line(input: &str) -> Result<TypingTarget, (usize, WordListProblem)> {
+    let mut target = TypingTarget::default();
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        match render_ascii_pair(rest) {
+            Ok((leftover, (render, ascii))) => {
+                if ascii.split('|').any(|a| a.is_empty()) {
+                    let offset = input.len() - rest.len() + render.len();
+                    return Err((offset, WordListProblem::EmptyReading));
+                }
+
+                let kana_len = kana::kana_prefix_len(render);
+                let alternatives = reading_alternatives(ascii);
+
+                // Text that is entirely kana keeps its explicit reading.
+                if kana_len < render.len() {
+                    push_kana(&mut target, &render[..kana_len], &alternatives)
+                        .map_err(|problem| (input.len() - rest.len(), problem))?;
+                    target.render.push(render[kana_len..].to_string());
+                } else {
+                    target.render.push(render.to_string());
+                }
+                target.ascii.push(alternatives);
+
+                rest = leftover;
+            }
+            Err(_) if kana::kana_prefix_len(rest) == rest.len() => {
+                push_kana(&mut target, rest, &[])
+                    .map_err(|problem| (input.len() - rest.len(), problem))?;
+                break;
+            }
+            Err(_) => {
+                let (offset, problem) = diagnose(rest);
+                return Err((input.len() - rest.len() + offset, problem));
+            }
+        }
+    }
+
+    let glyphs = target
+        .render
+        .drain(..)
+        .zip(target.ascii.drain(..))
+        .collect();
+    let (render, ascii) = kana::join_sokuon(glyphs).into_iter().unzip();
+    target.render = render;
+    target.ascii = ascii;
+
+    Ok(target)
+}
+
+/// Returns every accepted way of typing a reading like `yon|yo`.
+fn reading_alternatives(reading: &str) -> Vec<String> {
+    let mut alternatives: Vec<String> = vec![];
+
+    for alternative in reading.split('|').flat_map(kana::reading_alternatives) {
+        if !alternatives.contains(&alternative) {
+            alternatives.push(alternative);
+        }
+    }
+
+    alternatives
+}
+
+fn push_kana(
+    target: &mut TypingTarget,
+    input: &str,
+    next: &[String],
+) -> Result<(), WordListProblem> {
+    let glyphs = kana::to_romaji(input, next)
+        .ok_or_else(|| WordListProblem::UnreadableKana(input.to_string()))?;
+
+    for (render, ascii) in glyphs {
+        target.render.push(render);
+        target.ascii.push(ascii);
+    }
+
+    Ok(())
+}
+
+/// Figures out why `render_ascii_pair` failed to parse `input`, returning the byte offset of
+/// the problem within `input`.
+fn diagnose(input: &str) -> (usize, WordListProblem) {
+    let is_paren = |c: char| c == '(' || c == ')';
+
+    let open = match input.find(is_paren) {
+        Some(i) => i,
+        None => return (0, WordListProblem::MissingReading(input.to_string())),
+    };
+
+    if input[open..].starts_with(')') {
+        return (open, WordListProblem::UnexpectedCloseParen);
+    }
+
+    if open == 0 {
+        return (0, WordListProblem::MissingText);
+    }
+
+    match input[open + 1..].find(is_paren) {
+        Some(0) if input[open + 1..].starts_with(')') => (open, WordListProblem::EmptyReading),
+        _ => (open, WordListProblem::UnclosedParen),
+    }
 }
 
 fn render_ascii_pair(input: &str) -> IResult<&str, (&str, &str)> {
@@ -155,5 +631,109 @@ fn render_ascii_pair(input: &str) -> IResult<&str, (&str, &str)> {
 }
 
 fn parens(input: &str) -> IResult<&str, &str> {
-    delimited(char('('), is_not(")\r\n"), char(')'))(input)
+    delimited(char('('), is_not("()\r\n"), char(')'))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(string: &str) -> (Vec<TypingTarget>, Vec<WordListDiagnostic>) {
+        let word_list = WordList {
+            kind: WordListKind::Parenthesized,
+            string: string.to_string(),
+            accepted_chars: None,
+        };
+
+        parse_word_list("test", &word_list)
+    }
+
+    fn problems(string: &str) -> Vec<(usize, usize, WordListProblem)> {
+        parse(string)
+            .1
+            .into_iter()
+            .map(|d| (d.line, d.column, d.problem))
+            .collect()
+    }
+
+    #[test]
+    fn either_reading() {
+        let (targets, _) = parse("四(yon|yo)");
+        assert_eq!(targets[0].ascii, vec![vec!["yon", "yonn", "yon'", "yo"]]);
+    }
+
+    #[test]
+    fn duplicate() {
+        assert_eq!(
+            problems("か\nき\n  か"),
+            vec![(3, 3, WordListProblem::Duplicate { line: 1 })]
+        );
+    }
+
+    #[test]
+    fn duplicate_is_kept() {
+        let (targets, _) = parse("か\nか");
+        assert_eq!(targets.len(), 2);
+    }
+
+    #[test]
+    fn ambiguous() {
+        // Both can be typed as `ji`.
+        assert_eq!(
+            problems("じ\nぢ"),
+            vec![(2, 1, WordListProblem::Ambiguous { line: 1 })]
+        );
+    }
+
+    #[test]
+    fn unclosed_paren() {
+        assert_eq!(
+            problems("か\n  き手(te"),
+            vec![(2, 5, WordListProblem::UnclosedParen)]
+        );
+    }
+
+    #[test]
+    fn unexpected_close_paren() {
+        assert_eq!(
+            problems("手)"),
+            vec![(1, 2, WordListProblem::UnexpectedCloseParen)]
+        );
+    }
+
+    #[test]
+    fn empty_reading() {
+        assert_eq!(
+            problems("大(oo)手()"),
+            vec![(1, 6, WordListProblem::EmptyReading)]
+        );
+        assert_eq!(
+            problems("手(te|)"),
+            vec![(1, 2, WordListProblem::EmptyReading)]
+        );
+    }
+
+    #[test]
+    fn missing_text() {
+        assert_eq!(problems("(te)"), vec![(1, 1, WordListProblem::MissingText)]);
+    }
+
+    #[test]
+    fn missing_reading() {
+        assert_eq!(
+            problems("ひだり手"),
+            vec![(
+                1,
+                1,
+                WordListProblem::MissingReading("ひだり手".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn errors_are_errors() {
+        let (_, diagnostics) = parse("手\nか\nか");
+        let errors: Vec<bool> = diagnostics.iter().map(|d| d.problem.is_error()).collect();
+        assert_eq!(errors, vec![true, false]);
+    }
 }
@@ -0,0 +1,215 @@
+//! Endless mode keeps generating waves once the map's own waves run out, each one a bit
+//! tougher than the last, until the player loses. The best result for each map is saved.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use rand::{prelude::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{EnemyData, MapData},
+    enemy::EnemyKind,
+    main_menu::{GameMode, MapSelection},
+    save, Currency, GameData, GameState, TaipoState, TextureHandles, Wave, WaveGroup, WaveState,
+    Waves,
+};
+
+pub struct EndlessPlugin;
+
+impl Plugin for EndlessPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(save::load::<EndlessRecords>("endless_records").unwrap_or_default())
+            .add_system_set(
+                SystemSet::on_update(TaipoState::Ready).with_system(
+                    generate_waves
+                        .system()
+                        .label("generate_waves")
+                        .before("spawn_enemies"),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::GameOver)
+                    .with_system(record_result.system().label("record_endless_result")),
+            );
+    }
+}
+
+/// How generated waves grow tougher. Stats start out at those of the enemy archetype and
+/// grow with each generated wave.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EndlessData {
+    /// Whether the map's own waves are played before the generated ones.
+    pub map_waves: bool,
+    /// Seconds before each generated wave starts.
+    pub delay: f32,
+    /// Seconds between enemies within a group.
+    pub interval: f32,
+    /// The number of enemies in the first generated wave, spread over all of the paths.
+    pub num: f32,
+    /// Enemies added for each wave after that.
+    pub num_per_wave: f32,
+    /// Hit points are multiplied by this for each wave.
+    pub hp_growth: f32,
+    /// Armor added for each wave. Fractions add up over several waves.
+    pub armor_per_wave: f32,
+    /// Speed is multiplied by this for each wave, up to `max_speed`.
+    pub speed_growth: f32,
+    pub max_speed: f32,
+}
+
+impl Default for EndlessData {
+    fn default() -> Self {
+        Self {
+            map_waves: true,
+            delay: 15.0,
+            interval: 2.0,
+            num: 6.0,
+            num_per_wave: 1.0,
+            hp_growth: 1.15,
+            armor_per_wave: 0.2,
+            speed_growth: 1.02,
+            max_speed: 40.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct EndlessRecord {
+    /// The number of waves that had started.
+    pub wave: usize,
+    /// Currency earned over the whole game.
+    pub score: u32,
+}
+
+/// The best endless mode results for each map.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct EndlessRecords {
+    pub maps: BTreeMap<String, EndlessRecord>,
+}
+
+impl EndlessRecords {
+    /// Playing the map's own waves first makes for quite a different game, so results are
+    /// kept separately for that.
+    fn key(map: &MapData, endless: &EndlessData) -> String {
+        let waves = if endless.map_waves {
+            "map_waves"
+        } else {
+            "generated"
+        };
+
+        format!("{}:{}", map.file, waves)
+    }
+
+    pub fn best(&self, map: &MapData, endless: &EndlessData) -> EndlessRecord {
+        self.maps
+            .get(&Self::key(map, endless))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Makes the `n`th generated wave, which sends a group of enemies down every path in the map.
+fn generate_wave(n: usize, endless: &EndlessData, game_data: &GameData, waves: &Waves) -> Wave {
+    let mut rng = thread_rng();
+
+    let enemies: Vec<(&EnemyKind, &EnemyData)> = game_data.enemies.iter().collect();
+
+    let num = (endless.num + endless.num_per_wave * n as f32).max(1.0) as usize;
+    let num_per_path = (num + waves.paths.len() - 1) / waves.paths.len();
+
+    let groups = waves
+        .paths
+        .iter()
+        .map(|(path, goal)| {
+            let (enemy, enemy_data) = enemies.choose(&mut rng).unwrap();
+
+            WaveGroup {
+                path: path.clone(),
+                goal: *goal,
+                enemy: (*enemy).clone(),
+                num: num_per_path,
+                hp: (enemy_data.hp as f32 * endless.hp_growth.powi(n as i32)).round() as u32,
+                armor: enemy_data.armor + (endless.armor_per_wave * n as f32) as u32,
+                speed: (enemy_data.speed * endless.speed_growth.powi(n as i32))
+                    .min(endless.max_speed.max(enemy_data.speed)),
+                bounty: enemy_data.bounty,
+                damage: enemy_data.damage,
+                attack_interval: enemy_data.attack_interval,
+                interval: endless.interval,
+                offset: 0.0,
+            }
+        })
+        .collect();
+
+    Wave {
+        groups,
+        delay: endless.delay,
+        boss: false,
+    }
+}
+
+fn generate_waves(
+    game_mode: Res<GameMode>,
+    game_state: Res<GameState>,
+    wave_state: Res<WaveState>,
+    mut waves: ResMut<Waves>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+) {
+    if *game_mode != GameMode::Endless || game_state.over {
+        return;
+    }
+
+    if wave_state.current < waves.waves.len() || waves.paths.is_empty() {
+        return;
+    }
+
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+
+    let wave = generate_wave(waves.generated, &game_data.endless, game_data, &waves);
+
+    waves.waves.push(wave);
+    waves.generated += 1;
+
+    info!("Generated endless wave {}", waves.waves.len());
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_result(
+    game_mode: Res<GameMode>,
+    wave_state: Res<WaveState>,
+    currency: Res<Currency>,
+    map_selection: Res<MapSelection>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    mut records: ResMut<EndlessRecords>,
+) {
+    if *game_mode != GameMode::Endless {
+        return;
+    }
+
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+    let map = &game_data.maps[map_selection.index];
+
+    let result = EndlessRecord {
+        wave: wave_state.waves_reached(),
+        score: currency.total_earned,
+    };
+
+    let best = records
+        .maps
+        .entry(EndlessRecords::key(map, &game_data.endless))
+        .or_default();
+
+    if result.wave < best.wave || (result.wave == best.wave && result.score <= best.score) {
+        return;
+    }
+
+    info!("New endless record for {}: {:?}", map.name, result);
+
+    *best = result;
+
+    save::save("endless_records", &*records);
+}
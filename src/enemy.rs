@@ -1,25 +1,29 @@
 use crate::{
-    healthbar::HealthBar, layer, ActionPanel, AnimationData, AnimationHandles, Armor, Currency,
-    Goal, HitPoints, Speed, StatusDownSprite, StatusEffects, StatusUpSprite, TaipoStage,
-    TextureHandles,
+    audio::GameplayEvent, healthbar::HealthBar, layer, ActionPanel, AnimationData,
+    AnimationHandles, Armor, Currency, Goal, HitPoints, Speed, StatusDownSprite, StatusEffects,
+    StatusUpSprite, TaipoStage, TaipoState, TextureHandles,
 };
 use bevy::{ecs::query::Or, prelude::*};
 use rand::{thread_rng, Rng};
+use serde::Deserialize;
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(animate.system())
-            .add_system(
-                death
-                    .system()
-                    .label("enemy_death")
-                    .before("update_currency_text"),
-            )
-            .add_system(movement.system())
-            .add_system(deal_damage.system())
-            .add_system_to_stage(TaipoStage::AfterUpdate, status_effect_appearance.system());
+        app.add_system_set(
+            SystemSet::on_update(TaipoState::Ready)
+                .with_system(animate.system())
+                .with_system(
+                    death
+                        .system()
+                        .label("enemy_death")
+                        .before("update_currency_text"),
+                )
+                .with_system(movement.system())
+                .with_system(deal_damage.system()),
+        )
+        .add_system_to_stage(TaipoStage::AfterUpdate, status_effect_appearance.system());
     }
 }
 #[derive(Bundle, Default)]
@@ -31,6 +35,8 @@ pub struct EnemyBundle {
     pub animation_state: AnimationState,
     pub direction: Direction,
     pub attack_timer: AttackTimer,
+    pub attack_damage: AttackDamage,
+    pub bounty: Bounty,
     pub hit_points: HitPoints,
     pub status_effects: StatusEffects,
     pub armor: Armor,
@@ -62,13 +68,17 @@ impl Default for Direction {
         Direction::Right
     }
 }
-#[derive(Default, Debug)]
+/// The name of an enemy archetype from the game data.
+#[derive(Clone, Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+#[serde(transparent)]
 pub struct EnemyKind(pub String);
 
 #[derive(Default, Debug)]
 pub struct EnemyPath {
     pub path: Vec<Vec2>,
     pub path_index: usize,
+    /// The goal that is attacked after reaching the end of the path.
+    pub goal: Option<Entity>,
 }
 
 #[derive(Default)]
@@ -86,12 +96,27 @@ impl Default for AttackTimer {
         Self(Timer::from_seconds(1.0, true))
     }
 }
+pub struct AttackDamage(pub u32);
+impl Default for AttackDamage {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+pub struct Bounty(pub u32);
+impl Default for Bounty {
+    fn default() -> Self {
+        Self(2)
+    }
+}
 fn death(
-    mut query: Query<(&mut AnimationState, &mut Transform, &HitPoints), Changed<HitPoints>>,
+    mut query: Query<
+        (&mut AnimationState, &mut Transform, &HitPoints, &Bounty),
+        Changed<HitPoints>,
+    >,
     mut currency: ResMut<Currency>,
     mut action_panel: ResMut<ActionPanel>,
 ) {
-    for (mut state, mut transform, hp) in query.iter_mut() {
+    for (mut state, mut transform, hp, bounty) in query.iter_mut() {
         if hp.current == 0 {
             match *state {
                 AnimationState::Corpse => {}
@@ -101,8 +126,8 @@ fn death(
                     let mut rng = thread_rng();
                     transform.rotate(Quat::from_rotation_z(rng.gen_range(-0.2..0.2)));
 
-                    currency.current = currency.current.saturating_add(2);
-                    currency.total_earned = currency.total_earned.saturating_add(2);
+                    currency.current = currency.current.saturating_add(bounty.0);
+                    currency.total_earned = currency.total_earned.saturating_add(bounty.0);
 
                     action_panel.update += 1;
                 }
@@ -112,19 +137,44 @@ fn death(
 }
 
 fn deal_damage(
+    mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(&mut AttackTimer, &AnimationState)>,
+    mut query: Query<(
+        Entity,
+        &mut AttackTimer,
+        &AttackDamage,
+        &AnimationState,
+        &EnemyPath,
+    )>,
     mut goal_query: Query<&mut HitPoints, With<Goal>>,
+    mut gameplay_events: EventWriter<GameplayEvent>,
 ) {
     // TODO this should really sync up with the animations somehow
 
-    for (mut timer, state) in query.iter_mut() {
+    for (entity, mut timer, damage, state, path) in query.iter_mut() {
         if let AnimationState::Attacking = state {
+            let goal = match path.goal {
+                Some(goal) => goal,
+                None => continue,
+            };
+
+            let mut hp = match goal_query.get_mut(goal) {
+                Ok(hp) => hp,
+                Err(_) => continue,
+            };
+
+            // When the game goes on until every goal has fallen, enemies that have nothing
+            // left to attack would otherwise keep the remaining waves from ever being won.
+            if hp.current == 0 {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+
             timer.0.tick(time.delta());
             if timer.0.finished() {
-                for mut hp in goal_query.iter_mut() {
-                    hp.current = hp.current.saturating_sub(1);
-                }
+                hp.current = hp.current.saturating_sub(damage.0);
+
+                gameplay_events.send(GameplayEvent::GoalDamaged);
             }
         }
     }
@@ -240,7 +290,7 @@ fn animate(
         timer.0.tick(time.delta());
         if timer.0.finished() {
             let anim_data = anim_data_assets
-                .get(anim_handles.handles.get(&kind.0).unwrap())
+                .get(anim_handles.handles.get(kind).unwrap())
                 .unwrap();
 
             // TODO there's really more to these animations than just cycling
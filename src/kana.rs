@@ -0,0 +1,403 @@
+//! Automatic romaji readings for hiragana and katakana, so that word lists don't need to
+//! spell out a reading for every kana.
+//!
+//! Readings follow what you would type into a Japanese IME, so `ん` is `nn`, `を` is `wo`,
+//! `づ` is `du` and `ー` is `-`. Those are what gets displayed, but the Kunrei-shiki and
+//! Nihon-shiki spellings in `ALTERNATIVES` are accepted too.
+
+#[rustfmt::skip]
+static KANA: &[(&str, &str)] = &[
+    ("あ", "a"), ("い", "i"), ("う", "u"), ("え", "e"), ("お", "o"),
+    ("か", "ka"), ("き", "ki"), ("く", "ku"), ("け", "ke"), ("こ", "ko"),
+    ("が", "ga"), ("ぎ", "gi"), ("ぐ", "gu"), ("げ", "ge"), ("ご", "go"),
+    ("さ", "sa"), ("し", "shi"), ("す", "su"), ("せ", "se"), ("そ", "so"),
+    ("ざ", "za"), ("じ", "ji"), ("ず", "zu"), ("ぜ", "ze"), ("ぞ", "zo"),
+    ("た", "ta"), ("ち", "chi"), ("つ", "tsu"), ("て", "te"), ("と", "to"),
+    ("だ", "da"), ("ぢ", "di"), ("づ", "du"), ("で", "de"), ("ど", "do"),
+    ("な", "na"), ("に", "ni"), ("ぬ", "nu"), ("ね", "ne"), ("の", "no"),
+    ("は", "ha"), ("ひ", "hi"), ("ふ", "fu"), ("へ", "he"), ("ほ", "ho"),
+    ("ば", "ba"), ("び", "bi"), ("ぶ", "bu"), ("べ", "be"), ("ぼ", "bo"),
+    ("ぱ", "pa"), ("ぴ", "pi"), ("ぷ", "pu"), ("ぺ", "pe"), ("ぽ", "po"),
+    ("ま", "ma"), ("み", "mi"), ("む", "mu"), ("め", "me"), ("も", "mo"),
+    ("や", "ya"), ("ゆ", "yu"), ("よ", "yo"),
+    ("ら", "ra"), ("り", "ri"), ("る", "ru"), ("れ", "re"), ("ろ", "ro"),
+    ("わ", "wa"), ("ゐ", "wi"), ("ゑ", "we"), ("を", "wo"), ("ん", "nn"),
+    ("ゔ", "vu"),
+    ("ぁ", "xa"), ("ぃ", "xi"), ("ぅ", "xu"), ("ぇ", "xe"), ("ぉ", "xo"),
+    ("ゃ", "xya"), ("ゅ", "xyu"), ("ょ", "xyo"), ("ゎ", "xwa"),
+    ("ゕ", "xka"), ("ゖ", "xke"), ("っ", "xtsu"),
+    ("ー", "-"),
+    // Combinations
+    ("きゃ", "kya"), ("きゅ", "kyu"), ("きょ", "kyo"),
+    ("ぎゃ", "gya"), ("ぎゅ", "gyu"), ("ぎょ", "gyo"),
+    ("しゃ", "sha"), ("しゅ", "shu"), ("しょ", "sho"), ("しぇ", "she"),
+    ("じゃ", "ja"), ("じゅ", "ju"), ("じょ", "jo"), ("じぇ", "je"),
+    ("ちゃ", "cha"), ("ちゅ", "chu"), ("ちょ", "cho"), ("ちぇ", "che"),
+    ("ぢゃ", "dya"), ("ぢゅ", "dyu"), ("ぢょ", "dyo"),
+    ("にゃ", "nya"), ("にゅ", "nyu"), ("にょ", "nyo"),
+    ("ひゃ", "hya"), ("ひゅ", "hyu"), ("ひょ", "hyo"),
+    ("びゃ", "bya"), ("びゅ", "byu"), ("びょ", "byo"),
+    ("ぴゃ", "pya"), ("ぴゅ", "pyu"), ("ぴょ", "pyo"),
+    ("みゃ", "mya"), ("みゅ", "myu"), ("みょ", "myo"),
+    ("りゃ", "rya"), ("りゅ", "ryu"), ("りょ", "ryo"),
+    ("うぃ", "wi"), ("うぇ", "we"), ("うぉ", "who"),
+    ("ゔぁ", "va"), ("ゔぃ", "vi"), ("ゔぇ", "ve"), ("ゔぉ", "vo"),
+    ("ふぁ", "fa"), ("ふぃ", "fi"), ("ふぇ", "fe"), ("ふぉ", "fo"),
+    ("つぁ", "tsa"), ("つぃ", "tsi"), ("つぇ", "tse"), ("つぉ", "tso"),
+    ("てぃ", "thi"), ("でぃ", "dhi"), ("とぅ", "twu"), ("どぅ", "dwu"),
+    ("いぇ", "ye"),
+];
+
+/// Other accepted ways of typing kana, on top of the readings in `KANA`.
+#[rustfmt::skip]
+static ALTERNATIVES: &[(&str, &str)] = &[
+    ("し", "si"), ("ち", "ti"), ("つ", "tu"), ("ふ", "hu"), ("じ", "zi"),
+    ("ぢ", "zi"), ("ぢ", "ji"), ("づ", "zu"), ("を", "o"), ("ん", "n'"),
+    ("ぁ", "la"), ("ぃ", "li"), ("ぅ", "lu"), ("ぇ", "le"), ("ぉ", "lo"),
+    ("ゃ", "lya"), ("ゅ", "lyu"), ("ょ", "lyo"), ("ゎ", "lwa"),
+    ("っ", "xtu"), ("っ", "ltu"), ("っ", "ltsu"),
+    ("しゃ", "sya"), ("しゅ", "syu"), ("しょ", "syo"), ("しぇ", "sye"),
+    ("じゃ", "zya"), ("じゅ", "zyu"), ("じょ", "zyo"), ("じぇ", "zye"),
+    ("じゃ", "jya"), ("じゅ", "jyu"), ("じょ", "jyo"), ("じぇ", "jye"),
+    ("ちゃ", "tya"), ("ちゅ", "tyu"), ("ちょ", "tyo"), ("ちぇ", "tye"),
+    ("ちゃ", "cya"), ("ちゅ", "cyu"), ("ちょ", "cyo"), ("ちぇ", "cye"),
+    ("ぢゃ", "zya"), ("ぢゅ", "zyu"), ("ぢょ", "zyo"),
+    ("ぢゃ", "ja"), ("ぢゅ", "ju"), ("ぢょ", "jo"),
+    ("うぃ", "whi"), ("うぇ", "whe"), ("ふぁ", "hwa"),
+];
+
+/// Explicit readings with more combinations of alternatives than this are only accepted
+/// as written.
+const MAX_READING_ALTERNATIVES: usize = 64;
+
+fn to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => std::char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Converts any katakana in `input` to hiragana.
+pub fn to_hiragana_str(input: &str) -> String {
+    input.chars().map(to_hiragana).collect()
+}
+
+fn is_sokuon(glyph: &str) -> bool {
+    glyph == "っ" || glyph == "ッ"
+}
+
+fn is_hatsuon(glyph: &str) -> bool {
+    glyph == "ん" || glyph == "ン"
+}
+
+/// Returns every accepted reading of the given hiragana, starting with the one from `KANA`.
+fn readings(kana: &str) -> Vec<String> {
+    KANA.iter()
+        .chain(ALTERNATIVES.iter())
+        .filter(|(k, _)| *k == kana)
+        .map(|(_, reading)| reading.to_string())
+        .collect()
+}
+
+/// Returns the length in chars of the glyph at the start of `chars` and its hiragana form, if
+/// it is kana that we know how to read.
+fn glyph(chars: &[char]) -> Option<(usize, &'static str)> {
+    // Combinations like きょ or ウェ need to be tried before their individual characters.
+    for len in &[2, 1] {
+        if chars.len() < *len {
+            continue;
+        }
+
+        let key: String = chars[..*len].iter().map(|c| to_hiragana(*c)).collect();
+
+        if let Some((kana, _)) = KANA.iter().find(|(kana, _)| *kana == key) {
+            return Some((*len, *kana));
+        }
+    }
+
+    None
+}
+
+/// Returns the length in bytes of the run of readable kana at the start of `input`.
+pub fn kana_prefix_len(input: &str) -> usize {
+    let chars: Vec<char> = input.chars().collect();
+
+    let mut i = 0;
+    while let Some((len, _)) = glyph(&chars[i..]) {
+        i += len;
+    }
+
+    chars[..i].iter().map(|c| c.len_utf8()).sum()
+}
+
+/// Splits a run of kana into glyphs and their accepted readings. The first reading of each
+/// glyph is the one to display. `next` has the readings of whatever follows the run, which
+/// we need to know if the run ends with `ん`.
+///
+/// A small `っ` is left with only the readings that type it on its own. `join_sokuon` takes
+/// care of doubling consonants once the glyph after it is known.
+///
+/// Returns `None` if `input` contains anything other than kana.
+pub fn to_romaji(input: &str, next: &[String]) -> Option<Vec<(String, Vec<String>)>> {
+    let chars: Vec<char> = input.chars().collect();
+
+    let mut glyphs: Vec<(String, Vec<String>)> = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (len, kana) = glyph(&chars[i..])?;
+        glyphs.push((chars[i..i + len].iter().collect(), readings(kana)));
+        i += len;
+    }
+
+    // ん can be typed as a single `n` when it can't be mistaken for the start of な, にゃ and
+    // friends. Work backwards so that the readings of the following glyph are already
+    // settled.
+
+    for i in (0..glyphs.len()).rev() {
+        let following: Vec<char> = match glyphs.get(i + 1) {
+            Some((_, readings)) => readings.iter().filter_map(|r| r.chars().next()).collect(),
+            None => next.iter().filter_map(|r| r.chars().next()).collect(),
+        };
+
+        if is_hatsuon(&glyphs[i].0) && following.iter().all(|c| !"aeiouyn'".contains(*c)) {
+            glyphs[i].1.push("n".to_string());
+        }
+    }
+
+    Some(glyphs)
+}
+
+/// Joins each small `っ` to the glyph after it. A `っ` doubles the consonant of whichever
+/// reading of that glyph is typed, so `っち` is `cchi` or `tti` but not `cti`, and that only
+/// works if both are typed as one. Typing `っ` on its own, like `xtsuchi`, still works.
+pub fn join_sokuon(glyphs: Vec<(String, Vec<String>)>) -> Vec<(String, Vec<String>)> {
+    let mut joined: Vec<(String, Vec<String>)> = vec![];
+
+    // Work backwards so that `っっ` doubles the consonant twice.
+    for (render, readings) in glyphs.into_iter().rev() {
+        if !is_sokuon(&render) || joined.is_empty() {
+            joined.push((render, readings));
+            continue;
+        }
+
+        let (next_render, next_readings) = joined.pop().unwrap();
+
+        let mut alternatives: Vec<String> = vec![];
+
+        let doubled = next_readings.iter().filter_map(|next_reading| {
+            let c = next_reading.chars().next()?;
+            if c.is_ascii_alphabetic() && !"aeioun".contains(c) {
+                Some(format!("{}{}", c, next_reading))
+            } else {
+                None
+            }
+        });
+
+        let separate = readings.iter().flat_map(|reading| {
+            next_readings
+                .iter()
+                .map(move |next_reading| format!("{}{}", reading, next_reading))
+        });
+
+        for alternative in doubled.chain(separate) {
+            if !alternatives.contains(&alternative) {
+                alternatives.push(alternative);
+            }
+        }
+
+        joined.push((format!("{}{}", render, next_render), alternatives));
+    }
+
+    joined.reverse();
+
+    joined
+}
+
+/// Returns the syllable from `table` with the longest reading that `input` starts with.
+fn longest_syllable(
+    table: &'static [(&'static str, &'static str)],
+    input: &str,
+) -> Option<&'static (&'static str, &'static str)> {
+    table
+        .iter()
+        .filter(|(_, reading)| input.starts_with(reading))
+        .max_by_key(|(_, reading)| reading.len())
+}
+
+/// Spells out a romaji reading in hiragana, or returns `None` if it can't be split up into
+/// the syllables we know about.
+fn reading_kana(reading: &str) -> Option<String> {
+    let is_vowel_or_y = |c: Option<char>| c.map_or(false, |c| "aeiouy".contains(c));
+
+    let mut kana = String::new();
+    let mut rest = reading;
+
+    while !rest.is_empty() {
+        let mut chars = rest.chars();
+        let (first, second, third) = (chars.next(), chars.next(), chars.next());
+
+        // ん is written as `n'`, as `nn` unless the second `n` starts the next syllable, as
+        // in `onna`, or as a single `n` before anything but a vowel or `y`.
+        if first == Some('n') {
+            let len = match second {
+                Some('\'') => Some(2),
+                Some('n') if !is_vowel_or_y(third) => Some(2),
+                _ if !is_vowel_or_y(second) => Some(1),
+                _ => None,
+            };
+
+            if let Some(len) = len {
+                kana.push('ん');
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        // A doubled consonant, or the `t` in `tchi`, is a small っ.
+        let doubled = match (first, second) {
+            (Some('t'), Some('c')) => true,
+            (Some(a), Some(b)) => a == b && a.is_ascii_alphabetic() && !"aeioun".contains(a),
+            _ => false,
+        };
+
+        if doubled {
+            kana.push('っ');
+            rest = &rest[1..];
+            continue;
+        }
+
+        // Prefer the readings in `KANA`, so that `o` is お rather than を.
+        let (syllable_kana, syllable_reading) = match (
+            longest_syllable(KANA, rest),
+            longest_syllable(ALTERNATIVES, rest),
+        ) {
+            (Some(k), Some(a)) if a.1.len() > k.1.len() => a,
+            (Some(k), _) => k,
+            (None, Some(a)) => a,
+            (None, None) => return None,
+        };
+
+        kana.push_str(syllable_kana);
+        rest = &rest[syllable_reading.len()..];
+    }
+
+    Some(kana)
+}
+
+/// Returns every accepted way of typing an explicit romaji reading, starting with `reading`
+/// itself. The reading is spelled out in kana first, so the alternatives are the same ones
+/// we'd accept for that kana. Readings that can't be split up into the syllables we know
+/// about, like the `mit` in `三(mit)つ`, are only accepted as written.
+pub fn reading_alternatives(reading: &str) -> Vec<String> {
+    let glyphs = match reading_kana(reading).and_then(|kana| to_romaji(&kana, &[])) {
+        Some(glyphs) => join_sokuon(glyphs),
+        None => return vec![reading.to_string()],
+    };
+
+    let mut alternatives = vec![String::new()];
+
+    for (_, readings) in glyphs.iter() {
+        alternatives = alternatives
+            .iter()
+            .flat_map(|a| readings.iter().map(move |r| format!("{}{}", a, r)))
+            .collect();
+
+        if alternatives.len() > MAX_READING_ALTERNATIVES {
+            return vec![reading.to_string()];
+        }
+    }
+
+    // The reading as written isn't always one of these, like the single `n` of `ん` in
+    // `onna`, but it's what gets displayed so it has to be accepted.
+    alternatives.retain(|a| a != reading);
+    alternatives.insert(0, reading.to_string());
+
+    alternatives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings_of(input: &str, next: &[&str]) -> Vec<Vec<String>> {
+        let next: Vec<String> = next.iter().map(|n| n.to_string()).collect();
+
+        join_sokuon(to_romaji(input, &next).unwrap())
+            .into_iter()
+            .map(|(_, readings)| readings)
+            .collect()
+    }
+
+    #[test]
+    fn hatsuon_at_end() {
+        assert_eq!(readings_of("かん", &[])[1], vec!["nn", "n'", "n"]);
+    }
+
+    #[test]
+    fn hatsuon_before_vowel_or_n() {
+        assert_eq!(readings_of("かんい", &[])[1], vec!["nn", "n'"]);
+        assert_eq!(readings_of("おんな", &[])[1], vec!["nn", "n'"]);
+        assert_eq!(readings_of("ほん", &["ya"])[1], vec!["nn", "n'"]);
+    }
+
+    #[test]
+    fn hatsuon_before_consonant() {
+        assert_eq!(readings_of("ほん", &["te"])[1], vec!["nn", "n'", "n"]);
+    }
+
+    #[test]
+    fn sokuon_doubles_typed_consonant() {
+        let readings = readings_of("まっち", &[]);
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[1][0], "cchi");
+        assert!(readings[1].contains(&"tti".to_string()));
+        assert!(readings[1].contains(&"xtsuchi".to_string()));
+        assert!(!readings[1].contains(&"cti".to_string()));
+    }
+
+    #[test]
+    fn sokuon_before_vowel() {
+        assert_eq!(readings_of("っあ", &[])[0][0], "xtsua");
+    }
+
+    #[test]
+    fn sokuon_at_end() {
+        assert_eq!(readings_of("あっ", &[])[1][0], "xtsu");
+    }
+
+    #[test]
+    fn not_kana() {
+        assert_eq!(to_romaji("か手", &[]), None);
+    }
+
+    #[test]
+    fn reading_hatsuon() {
+        assert_eq!(reading_alternatives("onna"), vec!["onna", "onnna", "on'na"]);
+        assert_eq!(reading_alternatives("kan'i"), vec!["kan'i", "kanni"]);
+    }
+
+    #[test]
+    fn reading_sokuon() {
+        assert!(reading_alternatives("kitte").contains(&"kixtsute".to_string()));
+        assert!(reading_alternatives("matcha").contains(&"maccha".to_string()));
+    }
+
+    #[test]
+    fn reading_as_written_comes_first() {
+        assert_eq!(reading_alternatives("tsu"), vec!["tsu", "tu"]);
+        assert_eq!(reading_alternatives("tu"), vec!["tu", "tsu"]);
+    }
+
+    #[test]
+    fn unknown_reading() {
+        assert_eq!(reading_alternatives("mit"), vec!["mit"]);
+    }
+
+    #[test]
+    fn too_many_reading_alternatives() {
+        // Every し can also be typed as `si`, which makes 128 combinations.
+        let reading = "shi".repeat(7);
+        assert_eq!(reading_alternatives(&reading), vec![reading]);
+    }
+}
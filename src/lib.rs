@@ -0,0 +1,2551 @@
+use std::time::Duration;
+
+use audio::{AudioSettings, GameplayEvent, SoundEffects, SoundPlugin, VolumeLevel};
+use bevy::{
+    ecs::schedule::{ReportExecutionOrderAmbiguities, StateError},
+    utils::HashMap,
+};
+use bevy::{
+    log::{Level, LogSettings},
+    prelude::*,
+    text::{Text2dSize, TextSection},
+};
+use bevy_kira_audio::{AudioPlugin, AudioSource};
+use bevy_tiled_prototype::{
+    tiled::{ObjectShape, PropertyValue},
+    Object,
+};
+use bevy_tiled_prototype::{Map, TiledMapCenter};
+use bindings::{command_target, ActionEvent, Bindings};
+use bullet::BulletPlugin;
+use data::{AnimationData, GameData, GameDataPlugin, TowerData};
+use endless::{EndlessPlugin, EndlessRecords};
+use enemy::{
+    AnimationState, AttackDamage, AttackTimer, Bounty, EnemyBundle, EnemyKind, EnemyPath,
+    EnemyPlugin,
+};
+use healthbar::HealthBarPlugin;
+use loading::LoadingPlugin;
+use main_menu::{GameMode, MainMenuPlugin, MapSelection, WordListSelection};
+use map_validation::{validate_map, MapProblem};
+use music::MusicPlugin;
+use pause::PausePlugin;
+use serde::Deserialize;
+use typing::{
+    typing_target_text_sections, AsciiModeEvent, TypingPlugin, TypingState, TypingStats,
+    TypingTarget, TypingTargetContainer, TypingTargetFinishedEvent, TypingTargetImage,
+    TypingTargetPriceContainer, TypingTargetPriceImage, TypingTargetPriceText, TypingTargetText,
+    TypingTargets,
+};
+
+use util::set_visible_recursive;
+
+#[macro_use]
+extern crate anyhow;
+
+mod audio;
+pub mod bindings;
+mod bullet;
+pub mod data;
+mod endless;
+mod enemy;
+mod healthbar;
+mod kana;
+mod layer;
+mod loading;
+mod main_menu;
+pub mod map_validation;
+mod music;
+mod pause;
+mod save;
+mod typing;
+mod util;
+
+pub static FONT_SIZE: f32 = 32.0;
+pub static FONT_SIZE_ACTION_PANEL: f32 = 32.0;
+pub static FONT_SIZE_INPUT: f32 = 32.0;
+pub static FONT_SIZE_LABEL: f32 = 24.0;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+enum TaipoStage {
+    AfterUpdate,
+    AfterPostUpdate,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum TaipoState {
+    Preload,
+    Load,
+    Spawn,
+    Ready,
+    MainMenu,
+    GameOver,
+    /// Swapping in the map that was picked in the main menu.
+    LoadMap,
+    /// Pushed on top of `Ready` while the game is paused.
+    Paused,
+}
+
+// This is getting quite bloated and probably contributing to a lot of
+// noise in the ambiguity detector.
+#[derive(Default)]
+pub struct GameState {
+    // Just so we can keep these in the correct order
+    tower_slots: Vec<Entity>,
+    over: bool,
+    ready: bool,
+    loss_condition: LossCondition,
+}
+
+/// Which goals need to be destroyed for the game to be lost. Maps pick one with a
+/// `loss_condition` property of `any` or `all`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LossCondition {
+    AnyGoal,
+    AllGoals,
+}
+impl Default for LossCondition {
+    fn default() -> Self {
+        LossCondition::AnyGoal
+    }
+}
+impl LossCondition {
+    fn is_met<'a>(&self, mut goals: impl Iterator<Item = &'a HitPoints>) -> bool {
+        match self {
+            LossCondition::AnyGoal => goals.any(|hp| hp.current == 0),
+            LossCondition::AllGoals => {
+                let mut any = false;
+                for hp in goals {
+                    if hp.current > 0 {
+                        return false;
+                    }
+                    any = true;
+                }
+                any
+            }
+        }
+    }
+}
+
+pub struct Currency {
+    current: u32,
+    total_earned: u32,
+}
+impl Default for Currency {
+    fn default() -> Self {
+        Currency {
+            current: 10,
+            total_earned: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TowerSelection {
+    selected: Option<Entity>,
+}
+
+/// Where to go once the game has been resumed, when leaving it from the pause menu. The
+/// game is resumed first so that `Ready` isn't left behind on the state stack.
+#[derive(Default)]
+struct LeaveAfterResume(Option<TaipoState>);
+
+struct ActionPanel {
+    actions: Vec<ActionPanelItem>,
+    entities: Vec<Entity>,
+    update: u32,
+}
+impl Default for ActionPanel {
+    fn default() -> Self {
+        ActionPanel {
+            actions: vec![],
+            entities: vec![],
+            update: 0,
+        }
+    }
+}
+struct ActionPanelItem {
+    icon: Handle<Texture>,
+    target: TypingTarget,
+    action: Action,
+    visible: bool,
+    disabled: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+enum Action {
+    None,
+    #[serde(skip)]
+    SelectTower(Entity),
+    GenerateMoney,
+    UnselectTower,
+    BuildTower(TowerType),
+    UpgradeTower,
+    SellTower,
+    SwitchLanguageMode,
+    ToggleMute,
+    ToggleAutoSubmit,
+    /// Submits the typing buffer.
+    Submit,
+    /// Deletes the last character in the typing buffer.
+    DeleteChar,
+    /// Deletes the last glyph's worth of the typing buffer, or anything at the end of it
+    /// that doesn't match.
+    DeleteWord,
+    /// Empties the typing buffer.
+    ClearLine,
+    /// Toggles clearing the typing buffer as soon as something wrong is typed.
+    ToggleAutoClear,
+    /// Changes a volume level by the given amount. Volume levels go from 0 to 1.
+    ChangeVolume(VolumeLevel, f32),
+    /// Starts another game with the same word lists.
+    Restart,
+    /// Goes back to the main menu.
+    MainMenu,
+    /// Pauses the game, or resumes it if it's already paused.
+    Pause,
+    /// Leaves the pause menu.
+    Resume,
+}
+impl Default for Action {
+    fn default() -> Self {
+        Action::None
+    }
+}
+
+struct CurrencyDisplay;
+struct DelayTimerDisplay;
+struct DelayTimerTimer(Timer);
+
+struct TowerSprite;
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Deserialize)]
+pub enum TowerType {
+    Basic,
+    Support,
+    Debuff,
+}
+
+#[derive(Default, Debug)]
+struct TowerStats {
+    level: u32,
+    range: f32,
+    damage: u32,
+    upgrade_price: u32,
+    speed: f32,
+}
+impl TowerStats {
+    fn from_data(tower_data: &TowerData, level: u32) -> Option<Self> {
+        tower_data.level(level).map(|stats| TowerStats {
+            level,
+            range: stats.range,
+            damage: stats.damage,
+            upgrade_price: stats.upgrade_price,
+            speed: stats.speed,
+        })
+    }
+}
+
+#[derive(Default)]
+struct TowerState {
+    timer: Timer,
+}
+
+struct Reticle;
+struct RangeIndicator;
+
+struct Goal;
+
+/// Marks everything that is spawned for a game, so that it can all be cleaned up before
+/// starting another one.
+struct GameMarker;
+
+struct TowerSlot;
+struct TowerSlotLabel;
+struct TowerSlotLabelBg;
+
+// Map and GameData don't really belong. Consolidate into AssetHandles?
+#[derive(Default)]
+pub struct TextureHandles {
+    pub tower_slot: Handle<Texture>,
+    pub coin_ui: Handle<Texture>,
+    pub upgrade_ui: Handle<Texture>,
+    pub back_ui: Handle<Texture>,
+    pub tower: Handle<Texture>,
+    pub tower_two: Handle<Texture>,
+    pub support_tower: Handle<Texture>,
+    pub support_tower_two: Handle<Texture>,
+    pub debuff_tower: Handle<Texture>,
+    pub debuff_tower_two: Handle<Texture>,
+    pub range_indicator: Handle<Texture>,
+    pub status_up: Handle<Texture>,
+    pub status_down: Handle<Texture>,
+    pub shuriken_tower_ui: Handle<Texture>,
+    pub support_tower_ui: Handle<Texture>,
+    pub debuff_tower_ui: Handle<Texture>,
+    pub timer_ui: Handle<Texture>,
+    pub sell_ui: Handle<Texture>,
+    pub bullet_shuriken: Handle<Texture>,
+    pub bullet_debuff: Handle<Texture>,
+    pub reticle: Handle<Texture>,
+    pub enemy_atlas: HashMap<EnemyKind, Handle<TextureAtlas>>,
+    pub enemy_atlas_texture: HashMap<EnemyKind, Handle<Texture>>,
+    pub tiled_map: Handle<Map>,
+    pub game_data: Handle<GameData>,
+    pub bindings: Handle<Bindings>,
+    pub sound_effects: Handle<SoundEffects>,
+}
+
+#[derive(Default)]
+pub struct AudioHandles {
+    pub sounds: HashMap<GameplayEvent, Handle<AudioSource>>,
+}
+
+#[derive(Default)]
+struct FontHandles {
+    jptext: Handle<Font>,
+    minimal: Handle<Font>,
+}
+
+#[derive(Default)]
+struct AnimationHandles {
+    handles: HashMap<EnemyKind, Handle<AnimationData>>,
+}
+
+pub struct HitPoints {
+    current: u32,
+    max: u32,
+}
+impl Default for HitPoints {
+    fn default() -> Self {
+        HitPoints { current: 1, max: 1 }
+    }
+}
+pub struct Speed(f32);
+impl Default for Speed {
+    fn default() -> Self {
+        Self(20.0)
+    }
+}
+
+/// A group of identical enemies within a wave.
+#[derive(Clone, Debug)]
+struct WaveGroup {
+    path: Vec<Vec2>,
+    /// The goal at the end of `path`.
+    goal: Option<Entity>,
+    enemy: EnemyKind,
+    num: usize,
+    hp: u32,
+    armor: u32,
+    speed: f32,
+    bounty: u32,
+    damage: u32,
+    attack_interval: f32,
+    interval: f32,
+    /// Seconds after the wave starts before this group's first enemy spawns.
+    offset: f32,
+}
+impl Default for WaveGroup {
+    fn default() -> Self {
+        WaveGroup {
+            path: vec![],
+            goal: None,
+            enemy: EnemyKind("skeleton".to_string()),
+            hp: 5,
+            num: 10,
+            armor: 0,
+            speed: 20.0,
+            bounty: 2,
+            damage: 1,
+            attack_interval: 1.0,
+            interval: 3.0,
+            offset: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Wave {
+    /// Groups of enemies that spawn alongside each other.
+    groups: Vec<WaveGroup>,
+    delay: f32,
+    /// Boss waves get their own music.
+    boss: bool,
+}
+impl Default for Wave {
+    fn default() -> Self {
+        Wave {
+            groups: vec![],
+            delay: 30.0,
+            boss: false,
+        }
+    }
+}
+impl Wave {
+    fn num(&self) -> usize {
+        self.groups.iter().map(|group| group.num).sum()
+    }
+}
+
+#[derive(Debug)]
+struct WaveState {
+    current: usize,
+    delay_timer: Timer,
+    started: bool,
+    /// Seconds since the current wave's delay timer finished.
+    elapsed: f32,
+    /// The number of enemies spawned from each of the current wave's groups.
+    group_spawned: Vec<usize>,
+    /// The number of enemies spawned in the current wave so far.
+    spawned: usize,
+    just_spawned: bool,
+}
+
+impl WaveState {
+    /// Returns the number of waves that have started spawning enemies.
+    fn waves_reached(&self) -> usize {
+        self.current + if self.spawned > 0 { 1 } else { 0 }
+    }
+}
+
+impl Default for WaveState {
+    fn default() -> Self {
+        WaveState {
+            current: 0,
+            delay_timer: Timer::from_seconds(30.0, false), // arbitrary, overwritten by wave
+            started: false,
+            elapsed: 0.0,
+            group_spawned: vec![],
+            spawned: 0,
+            just_spawned: false,
+        }
+    }
+}
+#[derive(Default)]
+struct Waves {
+    waves: Vec<Wave>,
+    /// Every path in the map along with the goal at its end, for generating waves.
+    paths: Vec<(Vec<Vec2>, Option<Entity>)>,
+    /// The number of waves generated in endless mode so far.
+    generated: usize,
+}
+
+#[derive(Default)]
+pub struct StatusEffects(Vec<StatusEffect>);
+impl StatusEffects {
+    pub fn get_max_sub_armor(&self) -> u32 {
+        self.0
+            .iter()
+            .filter_map(|e| match e.kind {
+                StatusEffectKind::SubArmor(amt) => Some(amt),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_total_add_damage(&self) -> u32 {
+        self.0
+            .iter()
+            .filter_map(|e| match e.kind {
+                StatusEffectKind::AddDamage(amt) => Some(amt),
+                _ => None,
+            })
+            .sum::<u32>()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub timer: Option<Timer>,
+}
+#[derive(Clone, Debug)]
+pub enum StatusEffectKind {
+    SubArmor(u32),
+    AddDamage(u32),
+}
+pub struct StatusUpSprite;
+pub struct StatusDownSprite;
+
+#[derive(Default)]
+pub struct Armor(u32);
+
+struct TowerChangedEvent;
+
+fn spawn_action_panel_item(
+    item: &ActionPanelItem,
+    container: Entity,
+    commands: &mut Commands,
+    font_handles: &Res<FontHandles>,
+    // just because we already had a resmut at the caller
+    texture_handles: &ResMut<TextureHandles>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) -> Entity {
+    let child = commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: if item.visible {
+                    Display::Flex
+                } else {
+                    Display::None
+                },
+                justify_content: JustifyContent::FlexStart,
+                align_items: AlignItems::Center,
+                size: Size::new(Val::Percent(100.0), Val::Px(42.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(item.target.clone())
+        .insert(item.action.clone())
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        margin: Rect {
+                            left: Val::Px(5.0),
+                            right: Val::Px(5.0),
+                            ..Default::default()
+                        },
+                        size: Size::new(Val::Auto, Val::Px(32.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(item.icon.clone().into()),
+                    ..Default::default()
+                })
+                .insert(TypingTargetImage);
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            bottom: Val::Px(0.0),
+                            left: Val::Px(2.0),
+                            ..Default::default()
+                        },
+                        padding: Rect {
+                            left: Val::Px(2.0),
+                            right: Val::Px(2.0),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        size: Size::new(Val::Px(38.0), Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+                    ..Default::default()
+                })
+                .insert(TypingTargetPriceContainer)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ImageBundle {
+                            style: Style {
+                                margin: Rect {
+                                    right: Val::Px(2.0),
+                                    ..Default::default()
+                                },
+                                size: Size::new(Val::Px(12.0), Val::Px(12.0)),
+                                ..Default::default()
+                            },
+                            material: materials.add(texture_handles.coin_ui.clone().into()),
+                            ..Default::default()
+                        })
+                        .insert(TypingTargetPriceImage);
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                ..Default::default()
+                            },
+                            text: Text::with_section(
+                                "0",
+                                TextStyle {
+                                    font: font_handles.jptext.clone(),
+                                    font_size: 16.0, // 16px in this font is just not quite 16px is it?
+                                    color: Color::WHITE,
+                                },
+                                TextAlignment::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(TypingTargetPriceText);
+                });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        ..Default::default()
+                    },
+                    text: Text {
+                        sections: typing_target_text_sections(
+                            item.target.render.join(""),
+                            font_handles.jptext.clone(),
+                            FONT_SIZE_ACTION_PANEL,
+                        ),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(TypingTargetText);
+        })
+        .id();
+
+    commands.entity(container).push_children(&[child]);
+
+    child
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_action_panel(
+    mut typing_target_query: Query<&mut TypingTarget>,
+    mut visible_query: Query<&mut Visible>,
+    mut style_query: Query<&mut Style>,
+    mut price_text_query: Query<&mut Text, With<TypingTargetPriceText>>,
+    target_children_query: Query<&Children, With<TypingTarget>>,
+    children_query: Query<&Children>,
+    tower_query: Query<(&TowerState, &TowerType, &TowerStats)>,
+    price_query: Query<(Entity, &Children), With<TypingTargetPriceContainer>>,
+    (actions, currency, selection, game_state, state): (
+        Res<ActionPanel>,
+        Res<Currency>,
+        Res<TowerSelection>,
+        Res<GameState>,
+        Res<State<TaipoState>>,
+    ),
+    (game_data_assets, texture_handles): (Res<Assets<GameData>>, Res<TextureHandles>),
+    mut gameplay_events: EventWriter<GameplayEvent>,
+) {
+    // The pause menu hides the action panel, and updates it again when it's done.
+    if !actions.is_changed() || *state.current() == TaipoState::Paused {
+        return;
+    }
+
+    let game_data = match game_data_assets.get(&texture_handles.game_data) {
+        Some(game_data) => game_data,
+        None => return,
+    };
+
+    info!("update actions");
+
+    let mut became_affordable = false;
+
+    for (item, entity) in actions.actions.iter().zip(actions.entities.iter()) {
+        let visible = match item.action {
+            Action::BuildTower(_) => match selection.selected {
+                Some(tower_slot) => tower_query.get(tower_slot).is_err(),
+                None => false,
+            },
+            Action::GenerateMoney => selection.selected.is_none(),
+            Action::UnselectTower => selection.selected.is_some(),
+            Action::UpgradeTower => match selection.selected {
+                Some(tower_slot) => match tower_query.get(tower_slot) {
+                    Ok((_, tower_type, stats)) => {
+                        stats.level < game_data.towers[tower_type].max_level()
+                    }
+                    Err(_) => false,
+                },
+                None => false,
+            },
+            Action::SellTower => match selection.selected {
+                Some(tower_slot) => tower_query.get(tower_slot).is_ok(),
+                None => false,
+            },
+            _ => false,
+        };
+
+        let price = match item.action {
+            Action::BuildTower(tower_type) => game_data.towers[&tower_type].price,
+            Action::UpgradeTower => match selection.selected {
+                Some(tower_slot) => match tower_query.get(tower_slot) {
+                    Ok((_, _, stats)) => stats.upgrade_price,
+                    Err(_) => 0,
+                },
+                None => 0,
+            },
+            _ => 0,
+        };
+
+        let disabled = price > currency.current;
+        let price_visible = visible && price > 0;
+
+        // visibility
+
+        if let Ok(mut style) = style_query.get_mut(*entity) {
+            style.display = if visible {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+
+        // Workaround for #838/#1135
+        set_visible_recursive(visible, *entity, &mut visible_query, &children_query);
+
+        // price
+
+        if let Ok(target_children) = target_children_query.get(*entity) {
+            for target_child in target_children.iter() {
+                if let Ok((price_entity, children)) = price_query.get(*target_child) {
+                    if let Ok(mut style) = style_query.get_mut(price_entity) {
+                        style.display = if price_visible {
+                            Display::Flex
+                        } else {
+                            Display::None
+                        };
+                    }
+
+                    // Workaround for #838/#1135
+                    set_visible_recursive(
+                        price_visible,
+                        price_entity,
+                        &mut visible_query,
+                        &children_query,
+                    );
+
+                    for child in children.iter() {
+                        if let Ok(mut text) = price_text_query.get_mut(*child) {
+                            text.sections[0].value = format!("{}", price);
+                        }
+                    }
+                    for child in children.iter() {
+                        if let Ok(mut text) = price_text_query.get_mut(*child) {
+                            text.sections[0].style.color =
+                                if disabled { Color::RED } else { Color::WHITE };
+                        }
+                    }
+                }
+            }
+        }
+
+        // we don't want invisible typing targets to get updated or make
+        // sounds or whatever. unaffordable ones are dimmed by update_target_text.
+        // nothing in the panel can be used once the game is over.
+        if let Ok(mut target) = typing_target_query.get_mut(*entity) {
+            // Only things that were already on screen become affordable. Otherwise, selecting
+            // a tower would make a sound for every affordable action.
+            if visible && !target.disabled && target.dimmed && !disabled {
+                became_affordable = true;
+            }
+
+            target.disabled = !visible || game_state.over;
+            target.dimmed = disabled;
+        }
+    }
+
+    if became_affordable {
+        gameplay_events.send(GameplayEvent::BecameAffordable);
+    }
+}
+
+// This currently does not work properly for status effects with timers, but
+// we don't have any of those in game yet.
+fn update_tower_status_effect_appearance(
+    mut commands: Commands,
+    query: Query<(Entity, &StatusEffects, &Children), (With<TowerType>, Changed<StatusEffects>)>,
+    up_query: Query<Entity, With<StatusUpSprite>>,
+    down_query: Query<Entity, With<StatusDownSprite>>,
+    tower_sprite_query: Query<&Sprite, With<TowerSprite>>,
+    texture_handles: Res<TextureHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, status_effects, children) in query.iter() {
+        let down = status_effects.get_max_sub_armor() > 0;
+        let up = status_effects.get_total_add_damage() > 0;
+
+        let sprite = children
+            .iter()
+            .filter_map(|child| tower_sprite_query.get(*child).ok())
+            .next()
+            .expect("no sprite for tower?");
+
+        for child in children.iter() {
+            match (down, down_query.get(*child)) {
+                (true, Err(_)) => {
+                    let down_ent = commands
+                        .spawn_bundle(SpriteBundle {
+                            material: materials.add(texture_handles.status_down.clone().into()),
+                            transform: Transform::from_translation(Vec3::new(
+                                sprite.size.x / 2.0 + 6.0,
+                                -12.0,
+                                layer::HEALTHBAR_BG,
+                            )),
+                            ..Default::default()
+                        })
+                        .insert(StatusDownSprite)
+                        .id();
+                    commands.entity(entity).push_children(&[down_ent]);
+                }
+                (false, Ok(down_ent)) => {
+                    commands.entity(down_ent).despawn_recursive();
+                }
+                _ => {}
+            }
+            match (up, up_query.get(*child)) {
+                (true, Err(_)) => {
+                    let up_ent = commands
+                        .spawn_bundle(SpriteBundle {
+                            material: materials.add(texture_handles.status_up.clone().into()),
+                            transform: Transform::from_translation(Vec3::new(
+                                sprite.size.x / 2.0 + 6.0,
+                                -12.0,
+                                layer::HEALTHBAR_BG,
+                            )),
+                            ..Default::default()
+                        })
+                        .insert(StatusUpSprite)
+                        .id();
+                    commands.entity(entity).push_children(&[up_ent]);
+                }
+                (false, Ok(up_ent)) => {
+                    commands.entity(up_ent).despawn_recursive();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn update_tower_status_effects(
+    mut reader: EventReader<TowerChangedEvent>,
+    query: Query<Entity, With<TowerState>>,
+    kind_query: Query<&TowerType>,
+    transform_query: Query<&Transform>,
+    stats_query: Query<&TowerStats>,
+    mut status_query: Query<&mut StatusEffects>,
+) {
+    if reader.iter().next().is_none() {
+        return;
+    }
+
+    let towers: Vec<_> = query.iter().collect();
+
+    for entity in towers.iter() {
+        if let Ok(mut status) = status_query.get_mut(*entity) {
+            status.0.clear();
+        }
+    }
+
+    for support_entity in towers.iter() {
+        if !matches!(kind_query.get(*support_entity), Ok(TowerType::Support)) {
+            continue;
+        }
+
+        for entity in towers.iter() {
+            if entity == support_entity {
+                continue;
+            }
+
+            if let Ok(mut status) = status_query.get_mut(*entity) {
+                let support_transform = transform_query.get(*support_entity).unwrap();
+                let support_stats = stats_query.get(*support_entity).unwrap();
+                let transform = transform_query.get(*entity).unwrap();
+
+                let dist = transform
+                    .translation
+                    .truncate()
+                    .distance(support_transform.translation.truncate());
+
+                if dist < support_stats.range {
+                    status.0.push(StatusEffect {
+                        kind: StatusEffectKind::AddDamage(1),
+                        timer: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn typing_target_finished_event(
+    mut commands: Commands,
+    mut tower_state_query: Query<(&mut TowerStats, &mut TowerState, &TowerType)>,
+    tower_children_query: Query<&Children, With<TowerSlot>>,
+    tower_sprite_query: Query<Entity, With<TowerSprite>>,
+    mut reticle_query: Query<(&mut Transform, &mut Visible), (With<Reticle>, Without<TowerSlot>)>,
+    action_query: Query<&Action>,
+    tower_transform_query: Query<&Transform, (With<TowerSlot>, Without<Reticle>)>,
+    (texture_handles, game_data_assets, mut typing_state): (
+        Res<TextureHandles>,
+        Res<Assets<GameData>>,
+        ResMut<TypingState>,
+    ),
+    (mut reader, mut action_events, mut toggle_events, mut tower_changed_events): (
+        EventReader<TypingTargetFinishedEvent>,
+        EventReader<ActionEvent>,
+        EventWriter<AsciiModeEvent>,
+        EventWriter<TowerChangedEvent>,
+    ),
+    (mut currency, mut selection, mut materials, mut action_panel, mut sound_settings): (
+        ResMut<Currency>,
+        ResMut<TowerSelection>,
+        ResMut<Assets<ColorMaterial>>,
+        ResMut<ActionPanel>,
+        ResMut<AudioSettings>,
+    ),
+    (mut state, mut leave_after_resume): (ResMut<State<TaipoState>>, ResMut<LeaveAfterResume>),
+    game_state: Res<GameState>,
+) {
+    let game_data = match game_data_assets.get(&texture_handles.game_data) {
+        Some(game_data) => game_data,
+        None => return,
+    };
+
+    // Actions come from typing targets or from bound keys, and only typing a target takes
+    // us out of ascii mode.
+
+    let actions: Vec<(Option<Action>, bool)> = reader
+        .iter()
+        .map(|event| (action_query.get(event.entity).ok().cloned(), true))
+        .chain(
+            action_events
+                .iter()
+                .map(|event| (Some(event.0.clone()), false)),
+        )
+        .collect();
+
+    for (maybe_action, typed) in actions {
+        info!("typing_target_finished");
+
+        let mut toggled_ascii_mode = false;
+
+        if let Some(action) = &maybe_action {
+            info!("Processing action: {:?}", action);
+
+            if let Action::GenerateMoney = *action {
+                currency.current = currency.current.saturating_add(1);
+                currency.total_earned = currency.total_earned.saturating_add(1);
+            } else if let Action::SelectTower(tower) = *action {
+                selection.selected = Some(tower);
+                action_panel.update += 1;
+            } else if let Action::UnselectTower = *action {
+                selection.selected = None;
+                action_panel.update += 1;
+            } else if let Action::SwitchLanguageMode = *action {
+                toggle_events.send(AsciiModeEvent::Toggle);
+                toggled_ascii_mode = true;
+                action_panel.update += 1;
+            } else if let Action::ToggleMute = *action {
+                sound_settings.mute = !sound_settings.mute;
+            } else if let Action::ChangeVolume(level, amount) = *action {
+                sound_settings.change_volume(level, amount);
+            } else if let Action::Restart = *action {
+                if let Err(e) = leave_game(&mut state, &mut leave_after_resume, TaipoState::Spawn) {
+                    warn!("Couldn't restart: {:?}", e);
+                }
+            } else if let Action::MainMenu = *action {
+                if let Err(e) =
+                    leave_game(&mut state, &mut leave_after_resume, TaipoState::MainMenu)
+                {
+                    warn!("Couldn't go back to the main menu: {:?}", e);
+                }
+            } else if let Action::Pause = *action {
+                // The game may have ended earlier in this frame, with its transition to the
+                // game over screen already queued.
+                let result = match state.current().clone() {
+                    TaipoState::Ready if !game_state.over => state.push(TaipoState::Paused),
+                    TaipoState::Paused => state.pop(),
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    warn!("Couldn't pause or resume: {:?}", e);
+                }
+            } else if let Action::Resume = *action {
+                if *state.current() == TaipoState::Paused {
+                    if let Err(e) = state.pop() {
+                        warn!("Couldn't resume: {:?}", e);
+                    }
+                }
+            } else if let Action::ToggleAutoSubmit = *action {
+                typing_state.auto_submit = !typing_state.auto_submit;
+            } else if let Action::ToggleAutoClear = *action {
+                typing_state.auto_clear = !typing_state.auto_clear;
+            } else if let Action::UpgradeTower = *action {
+                if let Some(tower) = selection.selected {
+                    if let Ok((mut tower_stats, mut tower_state, tower_type)) =
+                        tower_state_query.get_mut(tower)
+                    {
+                        let upgrade_price = tower_stats.upgrade_price;
+
+                        if let Some(upgraded) = TowerStats::from_data(
+                            &game_data.towers[tower_type],
+                            tower_stats.level + 1,
+                        ) {
+                            if currency.current >= upgrade_price {
+                                tower_state
+                                    .timer
+                                    .set_duration(Duration::from_secs_f32(1.0 / upgraded.speed));
+                                *tower_stats = upgraded;
+
+                                currency.current -= upgrade_price;
+
+                                tower_changed_events.send(TowerChangedEvent);
+                            }
+                        }
+                    }
+                }
+
+                action_panel.update += 1;
+            } else if let Action::BuildTower(tower_type) = *action {
+                let tower_data = &game_data.towers[&tower_type];
+
+                if currency.current < tower_data.price {
+                    continue;
+                }
+                currency.current -= tower_data.price;
+
+                if let Some(tower) = selection.selected {
+                    let stats = TowerStats::from_data(tower_data, 1).unwrap();
+
+                    commands
+                        .entity(tower)
+                        .insert(TowerState {
+                            timer: Timer::from_seconds(1.0 / stats.speed, true),
+                        })
+                        .insert(stats)
+                        .insert(StatusEffects::default())
+                        .insert(tower_type);
+
+                    tower_changed_events.send(TowerChangedEvent);
+                }
+            } else if let Action::SellTower = *action {
+                if let Some(tower) = selection.selected {
+                    let refund = match tower_state_query.get_mut(tower) {
+                        Ok((_, _, tower_type)) => game_data.towers[tower_type].price / 2,
+                        Err(_) => 0,
+                    };
+
+                    commands
+                        .entity(tower)
+                        .remove::<TowerType>()
+                        .remove::<TowerStats>()
+                        .remove::<TowerState>()
+                        .remove::<StatusEffects>();
+
+                    if let Ok(children) = tower_children_query.get(tower) {
+                        for child in children.iter() {
+                            if let Ok(ent) = tower_sprite_query.get(*child) {
+                                commands.entity(ent).despawn();
+
+                                let new_child = commands
+                                    .spawn_bundle(SpriteBundle {
+                                        material: materials
+                                            .add(texture_handles.tower_slot.clone().into()),
+                                        transform: Transform::from_translation(Vec3::new(
+                                            0.0,
+                                            0.0,
+                                            layer::TOWER_SLOT,
+                                        )),
+                                        ..Default::default()
+                                    })
+                                    .insert(TowerSprite)
+                                    .id();
+
+                                commands.entity(tower).push_children(&[new_child]);
+                            }
+                        }
+                    }
+
+                    // TODO refund upgrade price too
+                    currency.current = currency.current.saturating_add(refund);
+
+                    tower_changed_events.send(TowerChangedEvent);
+                }
+            }
+
+            action_panel.update += 1;
+        }
+
+        if typed && !toggled_ascii_mode {
+            toggle_events.send(AsciiModeEvent::Disable);
+        }
+
+        for (mut reticle_transform, mut reticle_visible) in reticle_query.iter_mut() {
+            if let Some(tower) = selection.selected {
+                if let Ok(transform) = tower_transform_query.get(tower) {
+                    reticle_transform.translation.x = transform.translation.x;
+                    reticle_transform.translation.y = transform.translation.y;
+                }
+                reticle_visible.is_visible = true;
+            } else {
+                reticle_visible.is_visible = false;
+            }
+        }
+    }
+}
+
+/// Switches from the game to `next`, resuming it first if it's paused.
+fn leave_game(
+    state: &mut State<TaipoState>,
+    leave_after_resume: &mut LeaveAfterResume,
+    next: TaipoState,
+) -> Result<(), StateError> {
+    if *state.current() == TaipoState::Paused {
+        state.pop()?;
+        leave_after_resume.0 = Some(next);
+        Ok(())
+    } else {
+        state.set(next)
+    }
+}
+
+fn leave_after_resume(
+    mut state: ResMut<State<TaipoState>>,
+    mut leave_after_resume: ResMut<LeaveAfterResume>,
+) {
+    if let Some(next) = leave_after_resume.0.take() {
+        if let Err(e) = state.set(next) {
+            warn!("Couldn't leave the game: {:?}", e);
+        }
+    }
+}
+
+fn animate_reticle(mut query: Query<&mut Transform, With<Reticle>>, time: Res<Time>) {
+    for mut transform in query.iter_mut() {
+        let delta = time.delta_seconds();
+        transform.rotate(Quat::from_rotation_z(-2.0 * delta));
+    }
+}
+
+fn spawn_enemy(
+    group: &WaveGroup,
+    commands: &mut Commands,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    texture_handles: &TextureHandles,
+) {
+    let path = group.path.clone();
+    let point = path.get(0).unwrap();
+
+    let entity = commands
+        .spawn_bundle(SpriteSheetBundle {
+            transform: Transform::from_translation(Vec3::new(point.x, point.y, layer::ENEMY)),
+            sprite: TextureAtlasSprite {
+                index: 0,
+                ..Default::default()
+            },
+            texture_atlas: texture_handles.enemy_atlas[&group.enemy].clone(),
+            ..Default::default()
+        })
+        .insert(GameMarker)
+        .insert_bundle(EnemyBundle {
+            kind: group.enemy.clone(),
+            path: EnemyPath {
+                path,
+                goal: group.goal,
+                ..Default::default()
+            },
+            hit_points: HitPoints {
+                current: group.hp,
+                max: group.hp,
+            },
+            armor: Armor(group.armor),
+            speed: Speed(group.speed),
+            bounty: Bounty(group.bounty),
+            attack_damage: AttackDamage(group.damage),
+            attack_timer: AttackTimer(Timer::from_seconds(group.attack_interval, true)),
+            ..Default::default()
+        })
+        .id();
+
+    healthbar::spawn(
+        entity,
+        healthbar::HealthBar {
+            size: Vec2::new(16.0, 2.0),
+            offset: Vec2::new(0.0, 14.0),
+            show_full: false,
+            show_empty: false,
+        },
+        commands,
+        materials,
+    );
+}
+
+fn spawn_enemies(
+    mut commands: Commands,
+    waves: ResMut<Waves>,
+    mut wave_state: ResMut<WaveState>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    time: Res<Time>,
+    texture_handles: Res<TextureHandles>,
+    game_state: Res<GameState>,
+) {
+    if wave_state.just_spawned {
+        wave_state.just_spawned = false;
+    }
+
+    if !game_state.ready || game_state.over {
+        return;
+    }
+
+    let current_wave = match waves.waves.get(wave_state.current) {
+        Some(wave) => wave,
+        None => return,
+    };
+
+    // If we haven't started the delay timer for a new wave yet,
+    // go ahead and do that.
+
+    if !wave_state.started {
+        wave_state.started = true;
+        wave_state
+            .delay_timer
+            .set_duration(Duration::from_secs_f32(current_wave.delay));
+        wave_state.delay_timer.reset();
+        wave_state.elapsed = 0.0;
+        wave_state.group_spawned = vec![0; current_wave.groups.len()];
+        return;
+    }
+
+    // There's nothing to do until the delay timer is finished.
+
+    wave_state.delay_timer.tick(time.delta());
+    if !wave_state.delay_timer.finished() {
+        return;
+    }
+
+    // Each group spawns its first enemy once its offset has passed and then another one
+    // every interval, regardless of what the other groups are doing.
+
+    wave_state.elapsed += time.delta_seconds();
+
+    for (i, group) in current_wave.groups.iter().enumerate() {
+        while wave_state.group_spawned[i] < group.num
+            && group.offset + wave_state.group_spawned[i] as f32 * group.interval
+                <= wave_state.elapsed
+        {
+            spawn_enemy(group, &mut commands, &mut materials, &texture_handles);
+
+            wave_state.group_spawned[i] += 1;
+            wave_state.spawned += 1;
+            wave_state.just_spawned = true;
+        }
+    }
+
+    // that was the last enemy
+    if wave_state.spawned == current_wave.num() {
+        wave_state.current += 1;
+        wave_state.spawned = 0;
+        wave_state.started = false;
+    }
+}
+
+/// Lets everyone know when the last living enemy of a wave is defeated.
+fn check_wave_cleared(
+    wave_state: Res<WaveState>,
+    query: Query<&AnimationState, With<EnemyKind>>,
+    mut had_enemies: Local<bool>,
+    mut gameplay_events: EventWriter<GameplayEvent>,
+) {
+    let has_enemies = query
+        .iter()
+        .any(|state| !matches!(state, AnimationState::Corpse));
+
+    // Enemies can all be dead in the middle of a wave when they're spawned slowly enough,
+    // so wait until the wave has finished spawning.
+    if *had_enemies && !has_enemies && wave_state.spawned == 0 && !wave_state.just_spawned {
+        gameplay_events.send(GameplayEvent::WaveCleared);
+    }
+
+    *had_enemies = has_enemies;
+}
+
+fn update_timer_display(
+    mut query: Query<&mut Text, With<DelayTimerDisplay>>,
+    mut timer: ResMut<DelayTimerTimer>,
+    time: Res<Time>,
+    wave_state: Res<WaveState>,
+) {
+    timer.0.tick(time.delta());
+    if !timer.0.finished() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        let val = f32::max(
+            0.0,
+            (wave_state.delay_timer.duration() - wave_state.delay_timer.elapsed()).as_secs_f32(),
+        );
+
+        text.sections[0].value = format!("{:.1}", val);
+    }
+}
+
+fn shoot_enemies(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tower_query: Query<(
+        &Transform,
+        &mut TowerState,
+        &TowerStats,
+        &TowerType,
+        &StatusEffects,
+    )>,
+    enemy_query: Query<(Entity, &HitPoints, &Transform), With<EnemyKind>>,
+    texture_handles: Res<TextureHandles>,
+    time: Res<Time>,
+    mut gameplay_events: EventWriter<GameplayEvent>,
+) {
+    for (transform, mut tower_state, tower_stats, tower_type, status_effects) in
+        tower_query.iter_mut()
+    {
+        if let TowerType::Support = *tower_type {
+            continue;
+        }
+
+        tower_state.timer.tick(time.delta());
+        if !tower_state.timer.finished() {
+            continue;
+        }
+
+        // we are just naively iterating over every enemy right now. at some point we should
+        // investigate whether some spatial data structure is useful here. but there is overhead
+        // involved in maintaining one and I think it's unlikely that we'd break even with the
+        // small amount of enemies and towers we're dealing with here.
+
+        let mut in_range = enemy_query
+            .iter()
+            .filter(|(_, hp, _)| hp.current > 0)
+            .filter(|(_, _, enemy_transform)| {
+                let dist = enemy_transform
+                    .translation
+                    .truncate()
+                    .distance(transform.translation.truncate());
+
+                dist <= tower_stats.range
+            });
+
+        // right now, possibly coincidentally, this query seems to be iterating in the order that
+        // the enemies were spawned.
+        //
+        // with all enemies current walking at the same speed, that is equivalent to the enemy
+        // furthest along the path, which is the default behavior we probably want.
+        //
+        // other options might be to sort the in-range enemies and select
+        // - closest to tower
+        // - furthest along path
+        // - highest health
+        // - lowest health
+
+        if let Some((enemy, _, _)) = in_range.next() {
+            let mut bullet_translation = transform.translation;
+            bullet_translation.y += 24.0; // XXX magic sprite offset
+
+            let material = match tower_type {
+                TowerType::Basic => materials.add(texture_handles.bullet_shuriken.clone().into()),
+                TowerType::Debuff => materials.add(texture_handles.bullet_debuff.clone().into()),
+                _ => panic!(),
+            };
+
+            let status = match tower_type {
+                TowerType::Debuff => Some(StatusEffect {
+                    kind: StatusEffectKind::SubArmor(2),
+                    timer: None,
+                }),
+                _ => None,
+            };
+
+            let damage: u32 = tower_stats
+                .damage
+                .saturating_add(status_effects.get_total_add_damage());
+
+            bullet::spawn(
+                bullet_translation,
+                enemy,
+                damage,
+                100.0,
+                status,
+                &mut commands,
+                material,
+            );
+
+            gameplay_events.send(GameplayEvent::TowerFired);
+        }
+    }
+}
+
+fn update_currency_text(
+    currency: Res<Currency>,
+    mut currency_display_query: Query<&mut Text, With<CurrencyDisplay>>,
+) {
+    if !currency.is_changed() {
+        return;
+    }
+
+    for mut target in currency_display_query.iter_mut() {
+        target.sections[0].value = format!("{}", currency.current);
+    }
+}
+
+fn update_tower_appearance(
+    mut commands: Commands,
+    sprite_query: Query<Entity, With<TowerSprite>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tower_query: Query<(Entity, &TowerStats, &TowerType, &Children), Changed<TowerStats>>,
+    texture_handles: Res<TextureHandles>,
+    textures: Res<Assets<Texture>>,
+) {
+    for (parent, stats, tower_type, children) in tower_query.iter_mut() {
+        for child in children.iter() {
+            if let Ok(ent) = sprite_query.get(*child) {
+                commands.entity(ent).despawn();
+            }
+        }
+
+        let texture_handle = match (tower_type, stats.level) {
+            (TowerType::Basic, 1) => Some(texture_handles.tower.clone()),
+            (TowerType::Basic, 2) => Some(texture_handles.tower_two.clone()),
+            (TowerType::Support, 1) => Some(texture_handles.support_tower.clone()),
+            (TowerType::Support, 2) => Some(texture_handles.support_tower_two.clone()),
+            (TowerType::Debuff, 1) => Some(texture_handles.debuff_tower.clone()),
+            (TowerType::Debuff, 2) => Some(texture_handles.debuff_tower_two.clone()),
+            _ => None,
+        };
+
+        if let Some(texture_handle) = texture_handle {
+            let texture = textures.get(texture_handle.clone()).unwrap();
+
+            let new_child = commands
+                .spawn_bundle(SpriteBundle {
+                    material: materials.add(texture_handle.clone().into()),
+                    transform: Transform::from_translation(Vec3::new(
+                        0.0,
+                        (texture.size.height / 2) as f32 - 16.0,
+                        layer::TOWER,
+                    )),
+                    ..Default::default()
+                })
+                .insert(TowerSprite)
+                .id();
+
+            commands.entity(parent).push_children(&[new_child]);
+        }
+    }
+}
+
+// This only needs to run when TowerSelection is mutated or
+// when TowerStats changes. It doesn't seem possible to accomplish
+// that with bevy right now though. Keep an eye on Bevy #1313
+fn update_range_indicator(
+    selection: Res<TowerSelection>,
+    mut query: Query<(&mut Transform, &mut Visible), (With<RangeIndicator>, Without<TowerStats>)>,
+    tower_query: Query<(&Transform, &TowerStats), (With<TowerStats>, Without<RangeIndicator>)>,
+) {
+    if let Some(slot) = selection.selected {
+        if let Ok((tower_t, stats)) = tower_query.get(slot) {
+            if let Some((mut t, mut v)) = query.iter_mut().next() {
+                t.translation.x = tower_t.translation.x;
+                t.translation.y = tower_t.translation.y;
+
+                // range is a radius, sprite width is diameter
+                t.scale.x = stats.range * 2.0 / 722.0; // XXX magic sprite scaling factor
+                t.scale.y = stats.range * 2.0 / 722.0; // XXX magic sprite scaling factor
+
+                v.is_visible = true;
+            }
+        } else if let Some((_, mut v)) = query.iter_mut().next() {
+            v.is_visible = false;
+        }
+    } else if let Some((_, mut v)) = query.iter_mut().next() {
+        v.is_visible = false;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn check_game_over(
+    mut state: ResMut<State<TaipoState>>,
+    mut game_state: ResMut<GameState>,
+    query: Query<&AnimationState>,
+    goal_query: Query<&HitPoints, With<Goal>>,
+    waves: Res<Waves>,
+    wave_state: Res<WaveState>,
+) {
+    // Hm. This was triggering before the game started, so we'll just check
+    // to see if there's at least one wave.
+
+    if waves.waves.is_empty() {
+        return;
+    }
+
+    if !game_state.ready || game_state.over {
+        return;
+    }
+
+    // count the number of non-corpses on the screen if we're on the last wave.
+    // it takes a frame for those enemies to appear in the query, so also check
+    // that we didn't just spawn an enemy on this frame.
+
+    let over_win = wave_state.current == waves.waves.len()
+        && !wave_state.just_spawned
+        && query.iter().all(|x| matches!(x, AnimationState::Corpse));
+
+    let over_loss = game_state.loss_condition.is_met(goal_query.iter());
+
+    game_state.over = over_win || over_loss;
+
+    if !game_state.over {
+        return;
+    }
+
+    // This runs before typed actions are handled, so pausing in the same frame sees that the
+    // game is over and doesn't get in the way. If something else has queued a state change
+    // anyway, we'll check again next frame.
+    if let Err(e) = state.replace(TaipoState::GameOver) {
+        warn!("Couldn't end the game: {:?}", e);
+        game_state.over = false;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_game_over(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    currency: Res<Currency>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    goal_query: Query<&HitPoints, With<Goal>>,
+    mut typing_target_query: Query<&mut TypingTarget>,
+    font_handles: Res<FontHandles>,
+    typing_stats: Res<TypingStats>,
+    (game_mode, endless_records, wave_state, map_selection): (
+        Res<GameMode>,
+        Res<EndlessRecords>,
+        Res<WaveState>,
+        Res<MapSelection>,
+    ),
+    (texture_handles, game_data_assets): (Res<TextureHandles>, Res<Assets<GameData>>),
+) {
+    let over_win = !game_state.loss_condition.is_met(goal_query.iter());
+
+    // The game's typing targets shouldn't do anything anymore, but commands like "mute"
+    // should keep working.
+    for mut target in typing_target_query.iter_mut() {
+        if !target.fixed {
+            target.disabled = true;
+        }
+    }
+
+    // Pretty sure this draws under the UI, so we'll just carefully avoid UI stuff.
+    // A previous version of this used the UI, but it was causing JUST THE BACKGROUND
+    // of the action pane to disappear.
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer::OVERLAY_BG)),
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+            sprite: Sprite::new(Vec2::new(200.0, 180.0)),
+            ..Default::default()
+        })
+        .insert(GameMarker);
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 53.0, layer::OVERLAY)),
+            text: Text::with_section(
+                if over_win {
+                    format!("やった!\n{}円", currency.total_earned)
+                } else {
+                    format!("やってない!\n{}円", currency.total_earned)
+                },
+                TextStyle {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE,
+                    color: if over_win { Color::WHITE } else { Color::RED },
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(GameMarker);
+
+    let mut summary = format!(
+        "{:.0} WPM  {:.0}%",
+        typing_stats.wpm(),
+        typing_stats.accuracy()
+    );
+
+    let most_missed = typing_stats.most_missed(3);
+    if !most_missed.is_empty() {
+        let glyphs: Vec<&str> = most_missed.iter().map(|(glyph, _)| *glyph).collect();
+        summary.push_str(&format!("\nにがて: {}", glyphs.join(" ")));
+    }
+
+    if *game_mode == GameMode::Endless {
+        let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+        let best = endless_records.best(&game_data.maps[map_selection.index], &game_data.endless);
+
+        summary = format!(
+            "wave {}  best {} ({}円)\n{}",
+            wave_state.waves_reached(),
+            best.wave,
+            best.score,
+            summary
+        );
+    }
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, -6.0, layer::OVERLAY)),
+            text: Text::with_section(
+                summary,
+                TextStyle {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_LABEL,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(GameMarker);
+
+    // "tsuduku" means "to be continued."
+    let choices = [
+        ("つづく", Action::Restart, -48.0),
+        ("メニュー", Action::MainMenu, 48.0),
+    ];
+
+    for (word, action, x) in choices.iter() {
+        let entity = spawn_overlay_target(
+            word,
+            action.clone(),
+            Vec2::new(*x, -66.0),
+            88.0,
+            &mut commands,
+            &mut materials,
+            &font_handles,
+        );
+        commands.entity(entity).insert(GameMarker);
+    }
+}
+
+/// Spawns a command word for a menu drawn over the map, like the one on the game over
+/// screen.
+fn spawn_overlay_target(
+    word: &str,
+    action: Action,
+    position: Vec2,
+    width: f32,
+    commands: &mut Commands,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    font_handles: &Res<FontHandles>,
+) -> Entity {
+    let target = command_target(word);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(position.extend(layer::OVERLAY_BG)),
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+            sprite: Sprite::new(Vec2::new(width, FONT_SIZE_LABEL)),
+            ..Default::default()
+        })
+        .insert(target.clone())
+        .insert(action)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(Text2dBundle {
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    text: Text {
+                        alignment: TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                        sections: typing_target_text_sections(
+                            target.render.join(""),
+                            font_handles.jptext.clone(),
+                            FONT_SIZE_LABEL,
+                        ),
+                    },
+                    ..Default::default()
+                })
+                .insert(TypingTargetText);
+        })
+        .id()
+}
+
+/// Cleans up after a game so that another one can be started. This happens when going
+/// back to the main menu or starting a game, because a game can be left from a few
+/// different states.
+#[allow(clippy::too_many_arguments)]
+fn teardown_game(
+    mut commands: Commands,
+    query: Query<Entity, With<GameMarker>>,
+    mut game_state: ResMut<GameState>,
+    mut currency: ResMut<Currency>,
+    mut selection: ResMut<TowerSelection>,
+    mut action_panel: ResMut<ActionPanel>,
+    (mut waves, mut wave_state): (ResMut<Waves>, ResMut<WaveState>),
+    mut typing_targets: ResMut<TypingTargets>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    *game_state = GameState::default();
+    *currency = Currency::default();
+    *selection = TowerSelection::default();
+    *action_panel = ActionPanel::default();
+    *waves = Waves::default();
+    *wave_state = WaveState::default();
+    typing_targets.clear();
+}
+
+/// The actions in the action panel, in order.
+const ACTION_PANEL_ACTIONS: &[Action] = &[
+    Action::GenerateMoney,
+    Action::BuildTower(TowerType::Basic),
+    Action::BuildTower(TowerType::Support),
+    Action::BuildTower(TowerType::Debuff),
+    Action::UpgradeTower,
+    Action::SellTower,
+    Action::UnselectTower,
+];
+
+/// The number of items in the action panel, each of which needs a typing target of its own.
+pub const ACTION_PANEL_ITEMS: usize = ACTION_PANEL_ACTIONS.len();
+
+fn action_panel_icon(action: &Action, texture_handles: &TextureHandles) -> Handle<Texture> {
+    match action {
+        Action::GenerateMoney => texture_handles.coin_ui.clone(),
+        Action::BuildTower(TowerType::Basic) => texture_handles.shuriken_tower_ui.clone(),
+        Action::BuildTower(TowerType::Support) => texture_handles.support_tower_ui.clone(),
+        Action::BuildTower(TowerType::Debuff) => texture_handles.debuff_tower_ui.clone(),
+        Action::UpgradeTower => texture_handles.upgrade_ui.clone(),
+        Action::SellTower => texture_handles.sell_ui.clone(),
+        _ => texture_handles.back_ui.clone(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn startup_system(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    texture_handles: ResMut<TextureHandles>,
+    mut action_panel: ResMut<ActionPanel>,
+    mut typing_targets: ResMut<TypingTargets>,
+    font_handles: Res<FontHandles>,
+    currency: Res<Currency>,
+    bindings_assets: Res<Assets<Bindings>>,
+    word_list_selection: Res<WordListSelection>,
+) {
+    info!("startup");
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::FlexStart,
+                align_items: AlignItems::Center,
+                size: Size::new(Val::Auto, Val::Px(42.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+            ..Default::default()
+        })
+        .insert(GameMarker)
+        .with_children(|parent| {
+            parent.spawn_bundle(ImageBundle {
+                style: Style {
+                    margin: Rect {
+                        left: Val::Px(5.0),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Auto, Val::Px(32.0)),
+                    ..Default::default()
+                },
+                material: materials.add(texture_handles.coin_ui.clone().into()),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect {
+                            left: Val::Px(5.0),
+                            right: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        format!("{}", currency.current),
+                        TextStyle {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(CurrencyDisplay);
+            parent.spawn_bundle(ImageBundle {
+                style: Style {
+                    margin: Rect {
+                        left: Val::Px(5.0),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Auto, Val::Px(32.0)),
+                    ..Default::default()
+                },
+                material: materials.add(texture_handles.timer_ui.clone().into()),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect {
+                            left: Val::Px(5.0),
+                            right: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "30".to_string(),
+                        TextStyle {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(DelayTimerDisplay);
+        });
+
+    let action_container = commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::FlexEnd,
+                align_items: AlignItems::FlexEnd,
+                size: Size::new(Val::Percent(30.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+            ..Default::default()
+        })
+        .insert(TypingTargetContainer)
+        .insert(GameMarker)
+        .id();
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer::RETICLE)),
+            material: materials.add(texture_handles.reticle.clone().into()),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(Reticle)
+        .insert(GameMarker);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer::RANGE_INDICATOR)),
+            material: materials.add(texture_handles.range_indicator.clone().into()),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(RangeIndicator)
+        .insert(GameMarker);
+
+    // Only the money action is shown until a tower slot is selected.
+    let actions: Vec<ActionPanelItem> = ACTION_PANEL_ACTIONS
+        .iter()
+        .map(|action| ActionPanelItem {
+            icon: action_panel_icon(action, &texture_handles),
+            target: typing_targets.pop_front(),
+            action: action.clone(),
+            visible: matches!(action, Action::GenerateMoney),
+            disabled: false,
+        })
+        .collect();
+
+    let entities: Vec<Entity> = actions
+        .iter()
+        .map(|action| {
+            spawn_action_panel_item(
+                &action,
+                action_container,
+                &mut commands,
+                &font_handles,
+                &texture_handles,
+                &mut materials,
+            )
+        })
+        .collect();
+
+    action_panel.actions = actions;
+    action_panel.entities = entities;
+
+    if let Some(bindings) = bindings_assets.get(&texture_handles.bindings) {
+        for (word, action) in bindings.commands(&word_list_selection.lists) {
+            commands
+                .spawn()
+                .insert(command_target(&word))
+                .insert(action)
+                .insert(GameMarker);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_tower_slot_labels(
+    mut bg_query: Query<&mut Sprite, With<TowerSlotLabelBg>>,
+    query: Query<(&Text2dSize, &Parent), (With<TowerSlotLabel>, Changed<Text2dSize>)>,
+) {
+    for (size, parent) in query.iter() {
+        if let Ok(mut bg_sprite) = bg_query.get_mut(**parent) {
+            bg_sprite.size.x = size.size.width + 8.0;
+        }
+    }
+}
+
+fn start_game(mut game_state: ResMut<GameState>) {
+    game_state.ready = true;
+}
+
+/// Shows the first few problems with a map that can't be played, and a way back to the menu.
+/// The rest are only in the log.
+fn show_map_problems(
+    problems: &[MapProblem],
+    commands: &mut Commands,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    font_handles: &Res<FontHandles>,
+) {
+    const MAX_SHOWN: usize = 8;
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer::OVERLAY_BG)),
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.9).into()),
+            sprite: Sprite::new(Vec2::new(640.0, 300.0)),
+            ..Default::default()
+        })
+        .insert(GameMarker);
+
+    let mut text = format!("This map can't be played ({} problems):\n", problems.len());
+    for problem in problems.iter().take(MAX_SHOWN) {
+        text.push_str(&format!("\n{}", problem));
+    }
+    if problems.len() > MAX_SHOWN {
+        text.push_str(&format!(
+            "\n...and {} more in the log",
+            problems.len() - MAX_SHOWN
+        ));
+    }
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 20.0, layer::OVERLAY)),
+            text: Text::with_section(
+                text,
+                TextStyle {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_LABEL,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(GameMarker);
+
+    let entity = spawn_overlay_target(
+        "メニュー",
+        Action::MainMenu,
+        Vec2::new(0.0, -126.0),
+        88.0,
+        commands,
+        materials,
+        font_handles,
+    );
+    commands.entity(entity).insert(GameMarker);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_map_objects(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    mut typing_targets: ResMut<TypingTargets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut waves: ResMut<Waves>,
+    texture_handles: Res<TextureHandles>,
+    font_handles: Res<FontHandles>,
+    maps_query: Query<(&TiledMapCenter, &Handle<Map>)>,
+    maps: Res<Assets<Map>>,
+    game_data_assets: Res<Assets<GameData>>,
+    game_mode: Res<GameMode>,
+) {
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+
+    let (centered, map_handle) = maps_query
+        .iter()
+        .find(|(_, handle)| **handle == texture_handles.tiled_map)
+        .expect("The selected map should have been spawned.");
+
+    let map = match maps.get(map_handle) {
+        Some(map) => map,
+        None => panic!("Queried map not in assets?"),
+    };
+
+    let problems = validate_map(&map.map, game_data);
+    if !problems.is_empty() {
+        for problem in problems.iter() {
+            warn!("{}", problem);
+        }
+
+        show_map_problems(&problems, &mut commands, &mut materials, &font_handles);
+        return;
+    }
+
+    for grp in map.groups.iter() {
+        let mut tower_slots = grp
+            .objects
+            .iter()
+            .filter(|o| o.obj_type == "tower_slot")
+            .filter(|o| o.props.contains_key("index"))
+            .filter_map(|o| match o.props.get(&"index".to_string()) {
+                Some(PropertyValue::IntValue(index)) => Some((o, index)),
+                _ => None,
+            })
+            .collect::<Vec<(&Object, &i32)>>();
+
+        tower_slots.sort_by(|a, b| a.1.cmp(b.1));
+
+        for (obj, _index) in tower_slots {
+            // TODO can we use bevy_tiled_prototype::Object.transform_from_map for
+            // this? I tried once, and things seemed way off.
+
+            let transform = util::map_to_world(&map, obj.position, obj.size, 0.0, centered.0);
+
+            let mut label_bg_transform = transform.clone();
+            label_bg_transform.translation.y -= 32.0;
+            label_bg_transform.translation.z = layer::TOWER_SLOT_LABEL_BG;
+
+            // TODO we might be able to use ObjectReadyEvent for tower slots, but
+            // it's a bit awkward because we're adding the graphic as a child to
+            // make it easier to swap graphics.
+
+            let tower = commands
+                .spawn_bundle((transform, GlobalTransform::default()))
+                .insert(TowerSlot)
+                .insert(GameMarker)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(SpriteBundle {
+                            material: materials.add(texture_handles.tower_slot.clone().into()),
+                            transform: Transform::from_xyz(0.0, 0.0, layer::TOWER_SLOT),
+                            ..Default::default()
+                        })
+                        .insert(TowerSprite);
+                })
+                .id();
+
+            game_state.tower_slots.push(tower);
+
+            let target = typing_targets.pop_front();
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    transform: label_bg_transform,
+                    material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+                    sprite: Sprite::new(Vec2::new(108.0, FONT_SIZE_LABEL)),
+                    ..Default::default()
+                })
+                .insert(TowerSlotLabelBg)
+                .insert(GameMarker)
+                .insert(target.clone())
+                .insert(Action::SelectTower(tower))
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(Text2dBundle {
+                            transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                            text: Text {
+                                alignment: TextAlignment {
+                                    vertical: VerticalAlign::Center,
+                                    horizontal: HorizontalAlign::Center,
+                                },
+                                sections: typing_target_text_sections(
+                                    target.render.join(""),
+                                    font_handles.jptext.clone(),
+                                    FONT_SIZE_LABEL,
+                                ),
+                            },
+                            ..Default::default()
+                        })
+                        .insert(TypingTargetText)
+                        .insert(TowerSlotLabel);
+                });
+        }
+    }
+
+    let mut goals = vec![];
+
+    for goal in map
+        .groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == "goal")
+    {
+        let hp = match goal.props.get(&"hp".to_string()) {
+            Some(PropertyValue::IntValue(hp)) => *hp as u32,
+            _ => 10,
+        };
+
+        let transform =
+            util::map_to_world(&map, goal.position, goal.size, layer::ENEMY, centered.0);
+
+        let entity = commands
+            .spawn_bundle(SpriteBundle {
+                transform,
+                ..Default::default()
+            })
+            .insert(Goal)
+            .insert(GameMarker)
+            .insert(HitPoints {
+                current: hp,
+                max: hp,
+            })
+            .id();
+
+        healthbar::spawn(
+            entity,
+            healthbar::HealthBar {
+                size: Vec2::new(goal.size.x, goal.size.y),
+                offset: Vec2::new(0.0, 0.0),
+                show_full: true,
+                show_empty: true,
+            },
+            &mut commands,
+            &mut materials,
+        );
+
+        goals.push((entity, transform.translation.truncate()));
+    }
+
+    game_state.loss_condition = match map.map.properties.get("loss_condition") {
+        Some(PropertyValue::StringValue(v)) if v == "all" => LossCondition::AllGoals,
+        _ => LossCondition::AnyGoal,
+    };
+
+    let paths: HashMap<i32, Vec<Vec2>> = map
+        .groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == "enemy_path")
+        .filter_map(|o| match (&o.shape, o.props.get(&"index".to_string())) {
+            (ObjectShape::Polyline { points }, Some(PropertyValue::IntValue(index))) => {
+                Some((o, points, index))
+            }
+            (ObjectShape::Polygon { points }, Some(PropertyValue::IntValue(index))) => {
+                Some((o, points, index))
+            }
+            _ => None,
+        })
+        .map(|(obj, points, index)| {
+            let transformed: Vec<Vec2> = points
+                .iter()
+                .map(|(x, y)| {
+                    let transform = util::map_to_world(
+                        &map,
+                        Vec2::new(*x, *y) + obj.position,
+                        Vec2::new(0.0, 0.0),
+                        0.0,
+                        centered.0,
+                    );
+                    transform.translation.truncate()
+                })
+                .collect();
+
+            (*index, transformed)
+        })
+        .collect();
+
+    // Enemies attack whichever goal is closest to the end of their path.
+    let path_goals: HashMap<i32, Entity> = paths
+        .iter()
+        .filter_map(|(index, path)| {
+            let end = *path.last()?;
+
+            goals
+                .iter()
+                .min_by(|(_, a), (_, b)| {
+                    a.distance_squared(end)
+                        .partial_cmp(&b.distance_squared(end))
+                        .unwrap()
+                })
+                .map(|(goal, _)| (*index, *goal))
+        })
+        .collect();
+
+    let mut map_waves = map
+        .groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == "wave")
+        .filter(|o| o.props.contains_key("index"))
+        .filter_map(|o| match o.props.get(&"index".to_string()) {
+            Some(PropertyValue::IntValue(index)) => Some((o, *index)),
+            _ => None,
+        })
+        .collect::<Vec<(&Object, i32)>>();
+
+    map_waves.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let map_groups = map
+        .groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == "wave_group")
+        .collect::<Vec<&Object>>();
+
+    // A wave can describe a group of enemies itself, and more groups can be added with
+    // `wave_group` objects that refer to the wave's index.
+
+    for (map_wave, index) in map_waves {
+        let delay = match map_wave.props.get(&"delay".to_string()) {
+            Some(PropertyValue::FloatValue(v)) => *v,
+            _ => continue,
+        };
+
+        let boss = match map_wave.props.get(&"boss".to_string()) {
+            Some(PropertyValue::BoolValue(v)) => *v,
+            None => false,
+            _ => continue,
+        };
+
+        let mut groups = vec![];
+
+        if map_wave.props.contains_key("enemy") {
+            groups.extend(map_wave_group(map_wave, game_data, &paths, &path_goals));
+        }
+
+        for map_group in map_groups.iter().filter(|o| {
+            matches!(
+                o.props.get(&"wave".to_string()),
+                Some(PropertyValue::IntValue(wave)) if *wave == index
+            )
+        }) {
+            groups.extend(map_wave_group(map_group, game_data, &paths, &path_goals));
+        }
+
+        if groups.is_empty() {
+            continue;
+        }
+
+        waves.waves.push(Wave {
+            groups,
+            delay,
+            boss,
+        })
+    }
+
+    let mut path_indices: Vec<&i32> = paths.keys().collect();
+    path_indices.sort();
+
+    waves.paths = path_indices
+        .into_iter()
+        .map(|index| (paths[index].clone(), path_goals.get(index).copied()))
+        .collect();
+
+    // Endless mode may skip straight to generated waves.
+    if *game_mode == GameMode::Endless && !game_data.endless.map_waves {
+        waves.waves.clear();
+    }
+}
+
+/// Reads a group of enemies from a `wave` or `wave_group` map object. Groups name one of the
+/// enemy archetypes from the game data, and any of its stats may be overridden by the map.
+/// The overrides have already been range checked by `validate_map`.
+fn map_wave_group(
+    object: &Object,
+    game_data: &GameData,
+    paths: &HashMap<i32, Vec<Vec2>>,
+    path_goals: &HashMap<i32, Entity>,
+) -> Option<WaveGroup> {
+    let enemy = match object.props.get(&"enemy".to_string()) {
+        Some(PropertyValue::StringValue(v)) => EnemyKind(v.to_string()),
+        _ => return None,
+    };
+
+    let enemy_data = match game_data.enemies.get(&enemy) {
+        Some(enemy_data) => enemy_data,
+        None => {
+            warn!("Unknown enemy: {}", enemy.0);
+            return None;
+        }
+    };
+
+    let num = match object.props.get(&"num".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as usize,
+        _ => return None,
+    };
+
+    let interval = match object.props.get(&"interval".to_string()) {
+        Some(PropertyValue::FloatValue(v)) => *v,
+        _ => return None,
+    };
+
+    let hp = match object.props.get(&"hp".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as u32,
+        None => enemy_data.hp,
+        _ => return None,
+    };
+
+    let armor = match object.props.get(&"armor".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as u32,
+        None => enemy_data.armor,
+        _ => return None,
+    };
+
+    let speed = match object.props.get(&"speed".to_string()) {
+        Some(PropertyValue::FloatValue(v)) => *v,
+        None => enemy_data.speed,
+        _ => return None,
+    };
+
+    let bounty = match object.props.get(&"bounty".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as u32,
+        None => enemy_data.bounty,
+        _ => return None,
+    };
+
+    let damage = match object.props.get(&"damage".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as u32,
+        None => enemy_data.damage,
+        _ => return None,
+    };
+
+    let attack_interval = match object.props.get(&"attack_interval".to_string()) {
+        Some(PropertyValue::FloatValue(v)) => *v,
+        None => enemy_data.attack_interval,
+        _ => return None,
+    };
+
+    let path_index = match object.props.get(&"path_index".to_string()) {
+        Some(PropertyValue::IntValue(v)) => *v as i32,
+        _ => return None,
+    };
+
+    let path = match paths.get(&path_index) {
+        Some(p) => p.clone(),
+        None => {
+            warn!("Invalid path index");
+            return None;
+        }
+    };
+
+    let offset = match object.props.get(&"offset".to_string()) {
+        Some(PropertyValue::FloatValue(v)) => *v,
+        None => 0.0,
+        _ => return None,
+    };
+
+    Some(WaveGroup {
+        path,
+        goal: path_goals.get(&path_index).copied(),
+        enemy,
+        num,
+        hp,
+        armor,
+        speed,
+        bounty,
+        damage,
+        attack_interval,
+        interval,
+        offset,
+    })
+}
+
+fn check_spawn(
+    mut state: ResMut<State<TaipoState>>,
+    mut actions: ResMut<ActionPanel>,
+    typing_targets: Query<Entity, With<TypingTargetImage>>,
+    waves: Res<Waves>,
+) {
+    // this whole phase is probably not actually doing anything, but it does serve as a
+    // single place to put advance to the ready state from
+
+    // typing targets are probably the last thing to spawn because they're spawned by an event
+    // so maybe the game is ready if they are present.
+
+    if typing_targets.iter().next().is_none() {
+        return;
+    }
+
+    // Endless mode can start without any waves, but there's always a path once the map has
+    // spawned.
+    if waves.paths.is_empty() {
+        return;
+    }
+
+    // We need to force the action panel to update now that it has spawned
+    // because we didn't bother initializing it properly. Surprisingly this seems to work
+    // every time, but we should probably be on the lookout for actions not getting
+    // initialized
+
+    actions.update += 1;
+
+    state.replace(TaipoState::Ready).unwrap();
+}
+
+pub fn run() {
+    App::build()
+        .insert_resource(ReportExecutionOrderAmbiguities {})
+        // Make bevy_webgl2 shut up
+        .insert_resource(LogSettings {
+            filter: "bevy_webgl2=warn".into(),
+            level: Level::INFO,
+        })
+        .insert_resource(WindowDescriptor {
+            width: 720.,
+            height: 480.,
+            canvas: Some("#bevy-canvas".to_string()),
+            ..Default::default()
+        })
+        .add_state(TaipoState::Preload)
+        .add_plugins(DefaultPlugins)
+        .add_plugin(bevy_webgl2::WebGL2Plugin)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin)
+        .add_plugin(AudioPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(MusicPlugin)
+        .add_plugin(GameDataPlugin)
+        .add_plugin(TypingPlugin)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(EndlessPlugin)
+        // also, AppState::MainMenu from MainMenuPlugin
+        .add_plugin(LoadingPlugin)
+        // also, AppState::Preload from LoadingPlugin
+        // also, AppState::Load from LoadingPlugin
+        .add_event::<TowerChangedEvent>()
+        .add_system_set(
+            SystemSet::on_enter(TaipoState::Spawn)
+                .with_system(teardown_game.system().label("teardown_game"))
+                .with_system(spawn_map_objects.system().after("setup_typing_targets"))
+                .with_system(startup_system.system().after("setup_typing_targets")),
+        )
+        .add_system_set(
+            SystemSet::on_update(TaipoState::Spawn)
+                .with_system(check_spawn.system())
+                .with_system(update_action_panel.system()),
+        )
+        .add_system_set(SystemSet::on_enter(TaipoState::Ready).with_system(start_game.system()))
+        .add_system_set(
+            SystemSet::on_resume(TaipoState::Ready).with_system(leave_after_resume.system()),
+        )
+        .add_system_set(
+            SystemSet::on_enter(TaipoState::GameOver)
+                .with_system(show_game_over.system().after("record_endless_result")),
+        )
+        .add_system_set(
+            SystemSet::on_enter(TaipoState::MainMenu).with_system(teardown_game.system()),
+        )
+        .add_stage_after(
+            CoreStage::Update,
+            TaipoStage::AfterUpdate,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            CoreStage::PostUpdate,
+            TaipoStage::AfterPostUpdate,
+            SystemStage::parallel(),
+        )
+        .add_plugin(HealthBarPlugin)
+        .add_plugin(BulletPlugin)
+        .add_plugin(EnemyPlugin)
+        .init_resource::<GameState>()
+        .init_resource::<Currency>()
+        .init_resource::<TowerSelection>()
+        .init_resource::<LeaveAfterResume>()
+        .init_resource::<ActionPanel>()
+        .insert_resource(Waves::default())
+        .insert_resource(WaveState::default())
+        .insert_resource(DelayTimerTimer(Timer::from_seconds(0.1, true)))
+        .init_resource::<FontHandles>()
+        .init_resource::<TextureHandles>()
+        .init_resource::<AnimationHandles>()
+        .init_resource::<AudioHandles>()
+        .add_system(animate_reticle.system())
+        .add_system(
+            typing_target_finished_event
+                .system()
+                .label("typing_target_finished_event"),
+        )
+        .add_system_set(
+            SystemSet::on_update(TaipoState::Ready).with_system(
+                update_tower_status_effects
+                    .system()
+                    .label("update_tower_status_effects")
+                    .before("typing_target_finished_event"),
+            ),
+        )
+        .add_system(
+            update_currency_text
+                .system()
+                .label("update_currency_text")
+                .after("typing_target_finished_event"),
+        )
+        // Everything that moves the game along stops when it's paused or over.
+        .add_system_set(
+            SystemSet::on_update(TaipoState::Ready)
+                .with_system(shoot_enemies.system())
+                .with_system(update_timer_display.system())
+                .with_system(spawn_enemies.system().label("spawn_enemies"))
+                .with_system(check_wave_cleared.system().after("spawn_enemies"))
+                .with_system(
+                    check_game_over
+                        .system()
+                        .after("spawn_enemies")
+                        .before("typing_target_finished_event"),
+                ),
+        )
+        // update_actions_panel and update_range_indicator need to be aware of TowerStats components
+        // that get queued to spawn in the update stage.)
+        .add_system_to_stage(TaipoStage::AfterUpdate, update_action_panel.system())
+        .add_system_to_stage(TaipoStage::AfterUpdate, update_range_indicator.system())
+        // update_tower_appearance needs to detect added TowerStats components
+        .add_system_to_stage(TaipoStage::AfterUpdate, update_tower_appearance.system())
+        // update_tower_status_effect_appearance needs to detect an added or modified StatusEffects
+        // component, so it must run in a later stage.
+        .add_system_to_stage(
+            TaipoStage::AfterUpdate,
+            update_tower_status_effect_appearance.system(),
+        )
+        // update_tower_slot_labels uses Changed<CalculatedSize> which only works if we run after
+        // POST_UPDATE.
+        .add_system_to_stage(
+            TaipoStage::AfterPostUpdate,
+            update_tower_slot_labels.system(),
+        )
+        .run();
+}
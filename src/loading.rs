@@ -1,9 +1,10 @@
 use crate::{
-    layer, AnimationData, AnimationHandles, AudioHandles, FontHandles, GameData, TaipoState,
+    audio::SoundEffects, bindings::Bindings, enemy::EnemyKind, layer, main_menu::MapSelection,
+    AnimationData, AnimationHandles, AudioHandles, FontHandles, GameData, TaipoState,
     TextureHandles, TiledMapCenter, FONT_SIZE_ACTION_PANEL,
 };
 use bevy::{asset::LoadState, prelude::*};
-use bevy_tiled_prototype::{MapReadyEvent, TiledMapBundle};
+use bevy_tiled_prototype::{Map, MapReadyEvent, TiledMapBundle};
 
 pub struct LoadingPlugin;
 
@@ -14,19 +15,28 @@ struct MapReady {
 
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(
-            SystemSet::on_enter(TaipoState::Preload).with_system(preload_assets_startup.system()),
-        )
-        .add_system_set(
-            SystemSet::on_update(TaipoState::Preload).with_system(check_preload_assets.system()),
-        )
-        .add_system_set(
-            SystemSet::on_enter(TaipoState::Load).with_system(load_assets_startup.system()),
-        )
-        .add_system_set(
-            SystemSet::on_update(TaipoState::Load).with_system(check_load_assets.system()),
-        )
-        .add_system_set(SystemSet::on_exit(TaipoState::Load).with_system(load_cleanup.system()));
+        app.init_resource::<MapReady>()
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::Preload)
+                    .with_system(preload_assets_startup.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(TaipoState::Preload)
+                    .with_system(check_preload_assets.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::Load).with_system(load_assets_startup.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(TaipoState::Load).with_system(check_load_assets.system()),
+            )
+            .add_system_set(SystemSet::on_exit(TaipoState::Load).with_system(load_cleanup.system()))
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::LoadMap).with_system(load_map_startup.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(TaipoState::LoadMap).with_system(check_load_map.system()),
+            );
     }
 }
 
@@ -35,6 +45,10 @@ struct LoadingScreenMarker;
 // Our main font is gigantic, but I'd like to use some text on the loading screen. So let's load
 // a stripped down version.
 //
+// We also need the game data up front, because it tells us which enemy assets to load, the
+// bindings, because they tell us which command words to show, and the sound effects, because
+// they tell us which sounds to load.
+//
 // It probably makes way more sense to preload these things in JS or something, because the
 // wasm bundle is also gigantic, so we'll want some sort of loading indicator there too.
 //
@@ -43,9 +57,13 @@ fn preload_assets_startup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut font_handles: ResMut<FontHandles>,
+    mut texture_handles: ResMut<TextureHandles>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     font_handles.minimal = asset_server.load("fonts/NotoSans-Light-Min.ttf");
+    texture_handles.game_data = asset_server.load("data/game.ron");
+    texture_handles.bindings = asset_server.load("data/bindings.ron");
+    texture_handles.sound_effects = asset_server.load("data/sounds.ron");
 
     commands.spawn_bundle(UiCameraBundle::default());
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
@@ -82,14 +100,39 @@ fn preload_assets_startup(
 // TODO Show that loading screen
 fn check_preload_assets(
     font_handles: Res<FontHandles>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    bindings_assets: Res<Assets<Bindings>>,
+    sound_effects_assets: Res<Assets<SoundEffects>>,
     mut state: ResMut<State<TaipoState>>,
     asset_server: Res<AssetServer>,
 ) {
-    if let LoadState::Loaded = asset_server.get_load_state(font_handles.minimal.id) {
-        state.replace(TaipoState::Load).unwrap()
+    if !matches!(
+        asset_server.get_load_state(font_handles.minimal.id),
+        LoadState::Loaded
+    ) {
+        return;
     }
+
+    if game_data_assets.get(&texture_handles.game_data).is_none() {
+        return;
+    }
+
+    if bindings_assets.get(&texture_handles.bindings).is_none() {
+        return;
+    }
+
+    if sound_effects_assets
+        .get(&texture_handles.sound_effects)
+        .is_none()
+    {
+        return;
+    }
+
+    state.replace(TaipoState::Load).unwrap()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn load_assets_startup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -97,20 +140,20 @@ fn load_assets_startup(
     mut texture_handles: ResMut<TextureHandles>,
     mut animation_handles: ResMut<AnimationHandles>,
     mut audio_handles: ResMut<AudioHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    sound_effects_assets: Res<Assets<SoundEffects>>,
 ) {
     font_handles.jptext = asset_server.load("fonts/NotoSansJP-Light.otf");
 
-    let enemies = &["skeleton", "crab", "snake", "skeleton2", "deathknight"];
-
-    for enemy in enemies {
-        texture_handles.enemy_atlas_texture.insert(
-            enemy.to_string(),
-            asset_server.load(format!("textures/enemies/{}.png", enemy).as_str()),
-        );
-        animation_handles.handles.insert(
-            enemy.to_string(),
-            asset_server.load(format!("data/anim/{}.anim.ron", enemy).as_str()),
-        );
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+
+    for (kind, enemy) in game_data.enemies.iter() {
+        texture_handles
+            .enemy_atlas_texture
+            .insert(kind.clone(), asset_server.load(enemy.texture.as_str()));
+        animation_handles
+            .handles
+            .insert(kind.clone(), asset_server.load(enemy.animation.as_str()));
     }
 
     // Also we need all these loose textures because UI doesn't speak TextureAtlas
@@ -145,12 +188,21 @@ fn load_assets_startup(
 
     //
 
-    texture_handles.game_data = asset_server.load("data/game.ron");
-    texture_handles.tiled_map = asset_server.load("textures/level1.tmx");
+    // The first map doubles as the main menu's background until another one is picked.
+
+    texture_handles.tiled_map = asset_server.load(game_data.maps[0].file.as_str());
 
     //
 
-    audio_handles.wrong_character = asset_server.load("sounds/wrong_character.wav");
+    let sound_effects = sound_effects_assets
+        .get(&texture_handles.sound_effects)
+        .unwrap();
+
+    for (event, effect) in sound_effects.sounds.iter() {
+        audio_handles
+            .sounds
+            .insert(*event, asset_server.load(effect.file.as_str()));
+    }
 
     commands.spawn_bundle(TiledMapBundle {
         map_asset: texture_handles.tiled_map.clone(),
@@ -168,9 +220,8 @@ fn check_load_assets(
     mut texture_handles: ResMut<TextureHandles>,
     anim_handles: Res<AnimationHandles>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    game_data_assets: Res<Assets<GameData>>,
     anim_assets: Res<Assets<AnimationData>>,
-    mut map_ready: Local<MapReady>,
+    mut map_ready: ResMut<MapReady>,
     mut map_ready_events: EventReader<MapReadyEvent>,
 ) {
     for _event in map_ready_events.iter() {
@@ -189,7 +240,6 @@ fn check_load_assets(
         texture_handles.timer_ui.id,
         texture_handles.tower.id,
         texture_handles.bullet_shuriken.id,
-        texture_handles.game_data.id,
     ];
 
     if !matches!(
@@ -211,12 +261,6 @@ fn check_load_assets(
         return;
     }
 
-    // Uh, why is the thing above not enough for custom assets?
-    let game_data = game_data_assets.get(&texture_handles.game_data);
-    if game_data.is_none() {
-        return;
-    }
-
     // do these take an extra frame to make it into the assets resource after they stop being
     // NotLoaded or something?
     if anim_handles
@@ -228,27 +272,25 @@ fn check_load_assets(
         return;
     }
 
-    let names: Vec<String> = texture_handles
+    let kinds: Vec<EnemyKind> = texture_handles
         .enemy_atlas_texture
         .keys()
         .cloned()
         .collect();
 
-    for name in names {
+    for kind in kinds {
         let anim_data = anim_assets
-            .get(anim_handles.handles.get(&name.to_string()).unwrap())
+            .get(anim_handles.handles.get(&kind).unwrap())
             .unwrap();
 
         let atlas_handle = texture_atlases.add(TextureAtlas::from_grid(
-            texture_handles.enemy_atlas_texture[&name].clone(),
+            texture_handles.enemy_atlas_texture[&kind].clone(),
             Vec2::new(anim_data.width as f32, anim_data.height as f32),
             anim_data.cols,
             anim_data.rows,
         ));
 
-        texture_handles
-            .enemy_atlas
-            .insert(name.to_string(), atlas_handle);
+        texture_handles.enemy_atlas.insert(kind, atlas_handle);
     }
 
     state.replace(TaipoState::MainMenu).unwrap();
@@ -259,3 +301,55 @@ fn load_cleanup(mut commands: Commands, loading_query: Query<Entity, With<Loadin
         commands.entity(ent).despawn_recursive();
     }
 }
+
+/// Swaps out the map that is currently spawned if the player picked a different one.
+fn load_map_startup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_handles: ResMut<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    map_selection: Res<MapSelection>,
+    mut map_ready: ResMut<MapReady>,
+    map_query: Query<Entity, With<Handle<Map>>>,
+) {
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+    let map_data = &game_data.maps[map_selection.index];
+
+    let handle: Handle<Map> = asset_server.load(map_data.file.as_str());
+    if handle == texture_handles.tiled_map {
+        return;
+    }
+
+    info!("Loading map: {}", map_data.name);
+
+    for entity in map_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // Replacing the handle drops the last strong handle to the old map, which unloads it.
+    texture_handles.tiled_map = handle;
+    map_ready.ready = false;
+
+    commands.spawn_bundle(TiledMapBundle {
+        map_asset: texture_handles.tiled_map.clone(),
+        center: TiledMapCenter(true),
+        origin: Transform::from_scale(Vec3::new(1.0, 1.0, 1.0)),
+        ..Default::default()
+    });
+}
+
+fn check_load_map(
+    mut state: ResMut<State<TaipoState>>,
+    mut map_ready: ResMut<MapReady>,
+    mut map_ready_events: EventReader<MapReadyEvent>,
+) {
+    for _event in map_ready_events.iter() {
+        map_ready.ready = true;
+    }
+
+    if !map_ready.ready {
+        return;
+    }
+
+    state.replace(TaipoState::Spawn).unwrap();
+}
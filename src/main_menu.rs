@@ -1,7 +1,9 @@
 use bevy::prelude::*;
 use rand::{prelude::SliceRandom, thread_rng};
 
-use crate::typing::TypingTargets;
+use crate::bindings::{command_target, Bindings};
+use crate::data::MapData;
+use crate::typing::{InputMode, TypingState, TypingTargets};
 use crate::FontHandles;
 use crate::GameData;
 use crate::TaipoState;
@@ -14,26 +16,63 @@ pub struct MainMenuPlugin;
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<ButtonMaterials>()
+            .init_resource::<WordListSelection>()
+            .init_resource::<MapSelection>()
+            .init_resource::<GameMode>()
             .add_system_set(
                 SystemSet::on_enter(TaipoState::MainMenu).with_system(main_menu_startup.system()),
             )
             .add_system_set(
                 SystemSet::on_update(TaipoState::MainMenu)
                     .with_system(main_menu.system())
-                    .with_system(button_system.system()),
+                    .with_system(button_system.system())
+                    .with_system(input_mode_button_system.system())
+                    .with_system(map_button_system.system())
+                    .with_system(game_mode_button_system.system()),
             )
             .add_system_set(
                 SystemSet::on_exit(TaipoState::MainMenu).with_system(main_menu_cleanup.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::Spawn).with_system(
+                    setup_typing_targets
+                        .system()
+                        .label("setup_typing_targets")
+                        .after("teardown_game"),
+                ),
             );
     }
 }
 
 pub struct MainMenuMarker;
 
-#[derive(Clone)]
+pub struct InputModeButton;
+pub struct MapButton;
+pub struct GameModeButton;
+
+/// A choice of word lists in the menu. The chosen one is also kept around as a resource.
+#[derive(Clone, Default)]
 pub struct WordListSelection {
     label: String,
-    lists: Vec<String>,
+    pub lists: Vec<String>,
+}
+
+/// The map to play, as an index into `GameData::maps`.
+#[derive(Default)]
+pub struct MapSelection {
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameMode {
+    Normal,
+    /// Waves keep coming after the map's own waves run out.
+    Endless,
+}
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Normal
+    }
 }
 
 struct ButtonMaterials {
@@ -53,12 +92,60 @@ impl FromWorld for ButtonMaterials {
     }
 }
 
+fn input_mode_label(input_mode: InputMode) -> String {
+    match input_mode {
+        InputMode::Romaji => "Input: Romaji".to_string(),
+        InputMode::Kana => "Input: Kana / IME".to_string(),
+    }
+}
+
+fn game_mode_label(game_mode: GameMode) -> String {
+    match game_mode {
+        GameMode::Normal => "Mode: Normal".to_string(),
+        GameMode::Endless => "Mode: Endless".to_string(),
+    }
+}
+
+const BUTTON_TEXT: Color = Color::rgb(0.9, 0.9, 0.9);
+const RECOMMENDED_BUTTON_TEXT: Color = Color::rgb(1.0, 0.85, 0.3);
+
+fn map_label(map: &MapData) -> String {
+    format!("{} {}", map.name, "★".repeat(map.difficulty as usize))
+}
+
+/// Word list buttons that match the map's recommended word lists get a different color.
+fn word_list_button_color(selection: &WordListSelection, map: &MapData) -> Color {
+    let mut lists = selection.lists.clone();
+    let mut recommended = map.word_lists.clone();
+    lists.sort();
+    recommended.sort();
+
+    if lists == recommended {
+        RECOMMENDED_BUTTON_TEXT
+    } else {
+        BUTTON_TEXT
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn main_menu_startup(
     mut commands: Commands,
     font_handles: Res<FontHandles>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     button_materials: Res<ButtonMaterials>,
+    typing_state: Res<TypingState>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    mut map_selection: ResMut<MapSelection>,
+    game_mode: Res<GameMode>,
 ) {
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+
+    if map_selection.index >= game_data.maps.len() {
+        map_selection.index = 0;
+    }
+    let map = &game_data.maps[map_selection.index];
+
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -88,6 +175,37 @@ fn main_menu_startup(
                     ..Default::default()
                 })
                 .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Px(48.0)),
+                                margin: Rect {
+                                    bottom: Val::Px(20.0),
+                                    ..Rect::all(Val::Px(5.0))
+                                },
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .insert(MapButton)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    map_label(map),
+                                    TextStyle {
+                                        font: font_handles.jptext.clone(),
+                                        font_size: FONT_SIZE_LABEL,
+                                        color: BUTTON_TEXT,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+
                     let selections = vec![
                         WordListSelection {
                             label: "Kana".to_string(),
@@ -133,7 +251,7 @@ fn main_menu_startup(
                                         TextStyle {
                                             font: font_handles.jptext.clone(),
                                             font_size: FONT_SIZE_LABEL,
-                                            color: Color::rgb(0.9, 0.9, 0.9),
+                                            color: word_list_button_color(&selection, map),
                                         },
                                         Default::default(),
                                     ),
@@ -141,6 +259,65 @@ fn main_menu_startup(
                                 });
                             });
                     }
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Px(48.0)),
+                                margin: Rect {
+                                    top: Val::Px(20.0),
+                                    ..Rect::all(Val::Px(5.0))
+                                },
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .insert(InputModeButton)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    input_mode_label(typing_state.input_mode),
+                                    TextStyle {
+                                        font: font_handles.jptext.clone(),
+                                        font_size: FONT_SIZE_LABEL,
+                                        color: BUTTON_TEXT,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Px(48.0)),
+                                margin: Rect::all(Val::Px(5.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .insert(GameModeButton)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    game_mode_label(*game_mode),
+                                    TextStyle {
+                                        font: font_handles.jptext.clone(),
+                                        font_size: FONT_SIZE_LABEL,
+                                        color: BUTTON_TEXT,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
                 });
         });
 }
@@ -153,6 +330,48 @@ fn main_menu_cleanup(mut commands: Commands, main_menu_query: Query<Entity, With
     }
 }
 
+/// Starts every game with a fresh set of typing targets from the selected word lists, so
+/// that restarting doesn't carry anything over from the last game.
+fn setup_typing_targets(
+    selected: Res<WordListSelection>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    bindings_assets: Res<Assets<Bindings>>,
+    mut typing_targets: ResMut<TypingTargets>,
+    mut typing_state: ResMut<TypingState>,
+) {
+    let game_data = game_data_assets
+        .get(texture_handles.game_data.clone())
+        .unwrap();
+
+    let mut rng = thread_rng();
+
+    let mut possible_typing_targets: Vec<TypingTarget> = vec![];
+    for list in &selected.lists {
+        possible_typing_targets.extend(game_data.word_lists[&list.to_string()].clone());
+    }
+
+    possible_typing_targets.shuffle(&mut rng);
+
+    typing_targets.clear();
+    typing_targets.set_possible(possible_typing_targets, typing_state.input_mode);
+
+    // Command words are always available, so no other target should start the same way.
+    if let Some(bindings) = bindings_assets.get(&texture_handles.bindings) {
+        for (word, _) in bindings.commands(&selected.lists) {
+            typing_targets.reserve(&command_target(&word));
+        }
+    }
+
+    // If any of the lists accept everything, so do we.
+    typing_state.accepted_chars = selected
+        .lists
+        .iter()
+        .map(|list| game_data.accepted_chars.get(list))
+        .collect::<Option<Vec<_>>>()
+        .map(|accepted| accepted.into_iter().flat_map(|a| a.chars()).collect());
+}
+
 #[allow(clippy::type_complexity)]
 fn button_system(
     button_materials: Res<ButtonMaterials>,
@@ -161,30 +380,135 @@ fn button_system(
         (Changed<Interaction>, With<Button>),
     >,
     mut state: ResMut<State<TaipoState>>,
+    mut selected: ResMut<WordListSelection>,
+) {
+    for (interaction, mut material, word_list_selection) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+
+                *selected = word_list_selection.clone();
+
+                state.replace(TaipoState::LoadMap).unwrap();
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn input_mode_button_system(
+    button_materials: Res<ButtonMaterials>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>, &Children),
+        (Changed<Interaction>, With<InputModeButton>),
+    >,
+    mut text_query: Query<&mut Text>,
+    mut typing_state: ResMut<TypingState>,
+) {
+    for (interaction, mut material, children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+
+                typing_state.input_mode = match typing_state.input_mode {
+                    InputMode::Romaji => InputMode::Kana,
+                    InputMode::Kana => InputMode::Romaji,
+                };
+
+                for child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(*child) {
+                        text.sections[0].value = input_mode_label(typing_state.input_mode);
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn map_button_system(
+    button_materials: Res<ButtonMaterials>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>, &Children),
+        (Changed<Interaction>, With<MapButton>),
+    >,
+    word_list_button_query: Query<(&WordListSelection, &Children)>,
+    mut text_query: Query<&mut Text>,
+    mut map_selection: ResMut<MapSelection>,
     texture_handles: Res<TextureHandles>,
     game_data_assets: Res<Assets<GameData>>,
-    mut typing_targets: ResMut<TypingTargets>,
 ) {
-    for (interaction, mut material, word_list_selection) in interaction_query.iter_mut() {
+    for (interaction, mut material, children) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
                 *material = button_materials.pressed.clone();
 
-                let game_data = game_data_assets
-                    .get(texture_handles.game_data.clone())
-                    .unwrap();
+                let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
 
-                let mut rng = thread_rng();
+                map_selection.index = (map_selection.index + 1) % game_data.maps.len();
+                let map = &game_data.maps[map_selection.index];
 
-                let mut possible_typing_targets: Vec<TypingTarget> = vec![];
-                for list in &word_list_selection.lists {
-                    possible_typing_targets.extend(game_data.word_lists[&list.to_string()].clone());
+                for child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(*child) {
+                        text.sections[0].value = map_label(map);
+                    }
                 }
 
-                possible_typing_targets.shuffle(&mut rng);
-                typing_targets.possible = possible_typing_targets.into();
+                for (selection, children) in word_list_button_query.iter() {
+                    for child in children.iter() {
+                        if let Ok(mut text) = text_query.get_mut(*child) {
+                            text.sections[0].style.color = word_list_button_color(selection, map);
+                        }
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
 
-                state.replace(TaipoState::Spawn).unwrap();
+#[allow(clippy::type_complexity)]
+fn game_mode_button_system(
+    button_materials: Res<ButtonMaterials>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>, &Children),
+        (Changed<Interaction>, With<GameModeButton>),
+    >,
+    mut text_query: Query<&mut Text>,
+    mut game_mode: ResMut<GameMode>,
+) {
+    for (interaction, mut material, children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+
+                *game_mode = match *game_mode {
+                    GameMode::Normal => GameMode::Endless,
+                    GameMode::Endless => GameMode::Normal,
+                };
+
+                for child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(*child) {
+                        text.sections[0].value = game_mode_label(*game_mode);
+                    }
+                }
             }
             Interaction::Hovered => {
                 *material = button_materials.hovered.clone();
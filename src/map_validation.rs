@@ -0,0 +1,583 @@
+//! Checks the objects in a Tiled map before a game is spawned from it. Rather than stopping
+//! at the first problem, we collect all of them so that a map can be fixed in one go.
+
+use std::fmt;
+
+use bevy::utils::{HashMap, HashSet};
+use bevy_tiled_prototype::tiled::{self, ObjectShape, PropertyValue};
+
+use crate::{
+    bindings::{command_target, Bindings},
+    enemy::EnemyKind,
+    typing::{InputMode, TypingTargets},
+    GameData,
+};
+
+#[derive(Clone, Debug)]
+pub struct MapProblem {
+    /// The object with the problem, or `None` for problems with the map as a whole.
+    pub object: Option<(u32, String)>,
+    pub message: String,
+}
+
+impl MapProblem {
+    fn map(message: String) -> Self {
+        Self {
+            object: None,
+            message,
+        }
+    }
+
+    fn object(object: &tiled::Object, message: String) -> Self {
+        Self {
+            object: Some((object.id, object.name.clone())),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.object {
+            Some((id, name)) if name.is_empty() => write!(f, "Object {}: {}", id, self.message),
+            Some((id, name)) => write!(f, "Object {} ({}): {}", id, name, self.message),
+            None => write!(f, "Map: {}", self.message),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PropertyType {
+    Int,
+    Float,
+    String,
+    Bool,
+}
+
+impl PropertyType {
+    fn matches(&self, value: &PropertyValue) -> bool {
+        matches!(
+            (self, value),
+            (PropertyType::Int, PropertyValue::IntValue(_))
+                | (PropertyType::Float, PropertyValue::FloatValue(_))
+                | (PropertyType::String, PropertyValue::StringValue(_))
+                | (PropertyType::Bool, PropertyValue::BoolValue(_))
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PropertyType::Int => "int",
+            PropertyType::Float => "float",
+            PropertyType::String => "string",
+            PropertyType::Bool => "bool",
+        }
+    }
+}
+
+const WAVE_PROPERTIES: &[(&str, PropertyType, bool)] = &[
+    ("delay", PropertyType::Float, true),
+    ("boss", PropertyType::Bool, false),
+];
+
+/// Properties of a group of enemies, which can be on a `wave` or `wave_group` object.
+const GROUP_PROPERTIES: &[(&str, PropertyType, bool)] = &[
+    ("enemy", PropertyType::String, true),
+    ("num", PropertyType::Int, true),
+    ("interval", PropertyType::Float, true),
+    ("path_index", PropertyType::Int, true),
+    ("offset", PropertyType::Float, false),
+    ("hp", PropertyType::Int, false),
+    ("armor", PropertyType::Int, false),
+    ("speed", PropertyType::Float, false),
+    ("bounty", PropertyType::Int, false),
+    ("damage", PropertyType::Int, false),
+    ("attack_interval", PropertyType::Float, false),
+];
+
+/// The smallest values that numeric group properties may have, and whether that value
+/// itself is allowed.
+const GROUP_MINIMUMS: &[(&str, f32, bool)] = &[
+    ("num", 0.0, true),
+    ("interval", 0.0, true),
+    ("offset", 0.0, true),
+    ("hp", 1.0, true),
+    ("armor", 0.0, true),
+    ("speed", 0.0, false),
+    ("bounty", 0.0, true),
+    ("damage", 0.0, true),
+    ("attack_interval", 0.0, false),
+];
+
+/// Checks that `object` has a property called `name` of the right type, recording a problem
+/// if it doesn't. Properties that aren't `required` may be missing.
+fn check_property(
+    object: &tiled::Object,
+    name: &str,
+    property_type: PropertyType,
+    required: bool,
+    problems: &mut Vec<MapProblem>,
+) -> bool {
+    match object.properties.get(name) {
+        Some(value) if property_type.matches(value) => true,
+        Some(_) => {
+            problems.push(MapProblem::object(
+                object,
+                format!("Property \"{}\" should be {}", name, property_type.name()),
+            ));
+            false
+        }
+        None if required => {
+            problems.push(MapProblem::object(
+                object,
+                format!("Missing {} property \"{}\"", property_type.name(), name),
+            ));
+            false
+        }
+        None => false,
+    }
+}
+
+fn int_property(object: &tiled::Object, name: &str) -> Option<i32> {
+    match object.properties.get(name) {
+        Some(PropertyValue::IntValue(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Checks that the numeric property called `name`, if `object` has one, isn't less than
+/// `minimum`, or equal to it unless it's `inclusive`.
+fn check_minimum(
+    object: &tiled::Object,
+    name: &str,
+    minimum: f32,
+    inclusive: bool,
+    problems: &mut Vec<MapProblem>,
+) {
+    let value = match object.properties.get(name) {
+        Some(PropertyValue::IntValue(v)) => *v as f32,
+        Some(PropertyValue::FloatValue(v)) => *v,
+        _ => return,
+    };
+
+    if inclusive && value < minimum {
+        problems.push(MapProblem::object(
+            object,
+            format!("Property \"{}\" should be at least {}", name, minimum),
+        ));
+    } else if !inclusive && value <= minimum {
+        problems.push(MapProblem::object(
+            object,
+            format!("Property \"{}\" should be more than {}", name, minimum),
+        ));
+    }
+}
+
+/// Checks the `index` property of each of `objects`. Indices should count up from zero with
+/// no gaps or duplicates.
+fn check_indices(kind: &str, objects: &[&tiled::Object], problems: &mut Vec<MapProblem>) {
+    let mut by_index: HashMap<i32, Vec<&tiled::Object>> = HashMap::default();
+
+    for object in objects {
+        if check_property(object, "index", PropertyType::Int, true, problems) {
+            by_index
+                .entry(int_property(object, "index").unwrap())
+                .or_default()
+                .push(*object);
+        }
+    }
+
+    let mut indices: Vec<i32> = by_index.keys().cloned().collect();
+    indices.sort_unstable();
+
+    for index in indices.iter() {
+        let objects = &by_index[index];
+        if objects.len() > 1 {
+            for object in objects.iter() {
+                problems.push(MapProblem::object(
+                    object,
+                    format!("Duplicate {} index {}", kind, index),
+                ));
+            }
+        }
+
+        if *index < 0 {
+            for object in objects.iter() {
+                problems.push(MapProblem::object(
+                    object,
+                    format!("Negative {} index {}", kind, index),
+                ));
+            }
+        }
+    }
+
+    if let Some(max) = indices.last() {
+        for missing in (0..*max).filter(|i| !by_index.contains_key(i)) {
+            problems.push(MapProblem::map(format!(
+                "No {} with index {}",
+                kind, missing
+            )));
+        }
+    }
+}
+
+fn check_group(
+    object: &tiled::Object,
+    game_data: &GameData,
+    path_indices: &HashSet<i32>,
+    problems: &mut Vec<MapProblem>,
+) {
+    for (name, property_type, required) in GROUP_PROPERTIES.iter() {
+        check_property(object, name, *property_type, *required, problems);
+    }
+
+    for (name, minimum, inclusive) in GROUP_MINIMUMS.iter() {
+        check_minimum(object, name, *minimum, *inclusive, problems);
+    }
+
+    if let Some(PropertyValue::StringValue(enemy)) = object.properties.get("enemy") {
+        if !game_data.enemies.contains_key(&EnemyKind(enemy.clone())) {
+            problems.push(MapProblem::object(
+                object,
+                format!("Unknown enemy \"{}\"", enemy),
+            ));
+        }
+    }
+
+    if let Some(path_index) = int_property(object, "path_index") {
+        if !path_indices.contains(&path_index) {
+            problems.push(MapProblem::object(
+                object,
+                format!("No enemy path with index {}", path_index),
+            ));
+        }
+    }
+}
+
+/// Returns the goal closest to the end of `path`, which is the one its enemies attack.
+fn path_goal<'a>(path: &tiled::Object, goals: &[&'a tiled::Object]) -> Option<&'a tiled::Object> {
+    let end = match &path.shape {
+        ObjectShape::Polyline { points } | ObjectShape::Polygon { points } => points.last()?,
+        _ => return None,
+    };
+    let end = (path.x + end.0, path.y + end.1);
+
+    // Goals are tile objects, which are positioned by their bottom left corner.
+    let distance = |goal: &tiled::Object| {
+        let (x, y) = (goal.x + goal.width / 2.0, goal.y - goal.height / 2.0);
+        (x - end.0).powi(2) + (y - end.1).powi(2)
+    };
+
+    goals
+        .iter()
+        .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+        .copied()
+}
+
+fn objects_of_type<'a>(map: &'a tiled::Map, obj_type: &str) -> Vec<&'a tiled::Object> {
+    map.object_groups
+        .iter()
+        .flat_map(|grp| grp.objects.iter())
+        .filter(|o| o.obj_type == obj_type)
+        .collect()
+}
+
+/// Returns every problem with the objects in `map` that would keep a game from being
+/// spawned from it, or that would make it play differently than intended.
+pub fn validate_map(map: &tiled::Map, game_data: &GameData) -> Vec<MapProblem> {
+    let mut problems = vec![];
+
+    let tower_slots = objects_of_type(map, "tower_slot");
+    if tower_slots.is_empty() {
+        problems.push(MapProblem::map("No tower slots".to_string()));
+    }
+    check_indices("tower slot", &tower_slots, &mut problems);
+
+    let goals = objects_of_type(map, "goal");
+    if goals.is_empty() {
+        problems.push(MapProblem::map("No goal".to_string()));
+    }
+    for goal in goals.iter() {
+        check_property(goal, "hp", PropertyType::Int, false, &mut problems);
+    }
+
+    let paths = objects_of_type(map, "enemy_path");
+    if paths.is_empty() {
+        problems.push(MapProblem::map("No enemy paths".to_string()));
+    }
+    for path in paths.iter() {
+        match &path.shape {
+            ObjectShape::Polyline { points } | ObjectShape::Polygon { points } => {
+                if points.len() < 2 {
+                    problems.push(MapProblem::object(
+                        path,
+                        "Enemy path needs at least two points".to_string(),
+                    ));
+                }
+            }
+            _ => problems.push(MapProblem::object(
+                path,
+                "Enemy path should be a polyline or polygon".to_string(),
+            )),
+        }
+    }
+    check_indices("enemy path", &paths, &mut problems);
+
+    let path_indices: HashSet<i32> = paths
+        .iter()
+        .filter_map(|o| int_property(o, "index"))
+        .collect();
+
+    let waves = objects_of_type(map, "wave");
+    if waves.is_empty() {
+        problems.push(MapProblem::map("No waves".to_string()));
+    }
+    check_indices("wave", &waves, &mut problems);
+
+    let wave_indices: HashSet<i32> = waves
+        .iter()
+        .filter_map(|o| int_property(o, "index"))
+        .collect();
+
+    let groups = objects_of_type(map, "wave_group");
+    for group in groups.iter() {
+        check_group(group, game_data, &path_indices, &mut problems);
+
+        if check_property(group, "wave", PropertyType::Int, true, &mut problems) {
+            let wave = int_property(group, "wave").unwrap();
+            if !wave_indices.contains(&wave) {
+                problems.push(MapProblem::object(
+                    group,
+                    format!("No wave with index {}", wave),
+                ));
+            }
+        }
+    }
+
+    // A wave's own group is optional when `wave_group` objects add enemies to it.
+    let has_own_group = |wave: &tiled::Object| {
+        GROUP_PROPERTIES
+            .iter()
+            .any(|(name, _, required)| *required && wave.properties.contains_key(*name))
+    };
+
+    for wave in waves.iter() {
+        for (name, property_type, required) in WAVE_PROPERTIES.iter() {
+            check_property(wave, name, *property_type, *required, &mut problems);
+        }
+        check_minimum(wave, "delay", 0.0, true, &mut problems);
+
+        if has_own_group(wave) {
+            check_group(wave, game_data, &path_indices, &mut problems);
+        } else {
+            let index = int_property(wave, "index");
+            if !groups
+                .iter()
+                .any(|group| index.is_some() && int_property(group, "wave") == index)
+            {
+                problems.push(MapProblem::object(wave, "Wave has no enemies".to_string()));
+            }
+        }
+    }
+
+    // Enemies attack whichever goal is closest to the end of their path, so every goal should
+    // be at the end of a path that some wave uses.
+    let used_paths: HashSet<i32> = waves
+        .iter()
+        .filter(|o| has_own_group(o))
+        .chain(groups.iter())
+        .filter_map(|o| int_property(o, "path_index"))
+        .collect();
+
+    let reached_goals: HashSet<u32> = paths
+        .iter()
+        .filter(|o| int_property(o, "index").map_or(false, |i| used_paths.contains(&i)))
+        .filter_map(|path| path_goal(path, &goals))
+        .map(|goal| goal.id)
+        .collect();
+
+    for goal in goals.iter() {
+        if !reached_goals.contains(&goal.id) {
+            problems.push(MapProblem::object(
+                goal,
+                "No wave has a path that leads to this goal".to_string(),
+            ));
+        }
+    }
+
+    match map.properties.get("loss_condition") {
+        None => {}
+        Some(PropertyValue::StringValue(v)) if v == "any" || v == "all" => {}
+        Some(_) => problems.push(MapProblem::map(
+            "Property \"loss_condition\" should be \"any\" or \"all\"".to_string(),
+        )),
+    }
+
+    problems
+}
+
+/// Returns how many words from `lists` can be on screen at once without sharing a prefix
+/// with each other or with a command word, in whichever input mode allows fewer. Words are
+/// picked in the order they're listed, so the result doesn't change from run to run.
+pub fn unambiguous_words(game_data: &GameData, bindings: &Bindings, lists: &[String]) -> usize {
+    [InputMode::Romaji, InputMode::Kana]
+        .iter()
+        .map(|input_mode| {
+            let mut typing_targets = TypingTargets::default();
+
+            typing_targets.set_possible(
+                lists
+                    .iter()
+                    .filter_map(|list| game_data.word_lists.get(list))
+                    .flatten()
+                    .cloned()
+                    .collect(),
+                *input_mode,
+            );
+
+            for (word, _) in bindings.commands(lists) {
+                typing_targets.reserve(&command_target(&word));
+            }
+
+            std::iter::from_fn(|| typing_targets.pop_unambiguous()).count()
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::EnemyData;
+
+    /// The properties of a wave that has everything it needs.
+    const WAVE: &str = r#"
+        <property name="index" type="int" value="0"/>
+        <property name="delay" type="float" value="1"/>
+        <property name="enemy" value="crab"/>
+        <property name="num" type="int" value="1"/>
+        <property name="interval" type="float" value="1"/>
+        <property name="path_index" type="int" value="0"/>"#;
+
+    /// Validates a map with a tower slot, a goal at the end of a path, and a wave whose
+    /// properties are `WAVE` followed by `wave`, which replaces any that it repeats.
+    fn problems(wave: &str, objects: &str, map_properties: &str) -> Vec<String> {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" width="8" height="8" tilewidth="32" tileheight="32">
+ <properties>{}</properties>
+ <objectgroup id="1" name="objects">
+  <object id="1" type="tower_slot" x="0" y="64" width="32" height="32">
+   <properties><property name="index" type="int" value="0"/></properties>
+  </object>
+  <object id="2" type="goal" x="90" y="10" width="20" height="20"/>
+  <object id="3" type="enemy_path" x="0" y="0">
+   <properties><property name="index" type="int" value="0"/></properties>
+   <polyline points="0,0 100,0"/>
+  </object>
+  <object id="4" type="wave" x="0" y="128" width="32" height="32">
+   <properties>{}{}</properties>
+  </object>
+  {}
+ </objectgroup>
+</map>"#,
+            map_properties, WAVE, wave, objects
+        );
+
+        let map = tiled::parse(xml.as_bytes()).unwrap();
+
+        let mut game_data = GameData::default();
+        game_data.enemies.insert(
+            EnemyKind("crab".to_string()),
+            EnemyData {
+                texture: String::new(),
+                animation: String::new(),
+                hp: 1,
+                armor: 0,
+                speed: 1.0,
+                bounty: 0,
+                damage: 1,
+                attack_interval: 1.0,
+            },
+        );
+
+        validate_map(&map, &game_data)
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid() {
+        assert!(problems("", "", "").is_empty());
+    }
+
+    #[test]
+    fn property_type() {
+        assert_eq!(
+            problems(r#"<property name="num" value="1"/>"#, "", ""),
+            vec!["Object 4: Property \"num\" should be int"]
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(
+            problems(
+                r#"<property name="hp" type="int" value="0"/>
+                <property name="speed" type="float" value="0"/>"#,
+                "",
+                ""
+            ),
+            vec![
+                "Object 4: Property \"hp\" should be at least 1",
+                "Object 4: Property \"speed\" should be more than 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_enemy() {
+        assert_eq!(
+            problems(r#"<property name="enemy" value="dragon"/>"#, "", ""),
+            vec!["Object 4: Unknown enemy \"dragon\""]
+        );
+    }
+
+    #[test]
+    fn path_index() {
+        // The goal isn't reached either, since the only path isn't used.
+        assert_eq!(
+            problems(
+                r#"<property name="path_index" type="int" value="1"/>"#,
+                "",
+                ""
+            ),
+            vec![
+                "Object 4: No enemy path with index 1",
+                "Object 2: No wave has a path that leads to this goal",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        assert_eq!(
+            problems(
+                "",
+                r#"<object id="5" type="goal" x="0" y="250" width="20" height="20"/>"#,
+                ""
+            ),
+            vec!["Object 5: No wave has a path that leads to this goal"]
+        );
+    }
+
+    #[test]
+    fn loss_condition() {
+        assert!(problems("", "", r#"<property name="loss_condition" value="all"/>"#).is_empty());
+        assert_eq!(
+            problems("", "", r#"<property name="loss_condition" value="most"/>"#),
+            vec!["Map: Property \"loss_condition\" should be \"any\" or \"all\""]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Background music. A track is picked for whatever is going on in the game, and we
+//! crossfade between two channels when that changes.
+//!
+//! Tracks come from `game.ron`, and a map can replace any of them except `Menu` with a
+//! string property like `music_boss_wave`.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_kira_audio::{Audio, AudioChannel, AudioSource};
+use bevy_tiled_prototype::{tiled::PropertyValue, Map};
+use serde::Deserialize;
+
+use crate::{
+    audio::AudioSettings, GameData, GameState, Goal, HitPoints, TaipoState, TextureHandles,
+    WaveState, Waves,
+};
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MusicTracks>()
+            .init_resource::<MusicPlayer>()
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::MainMenu).with_system(load_tracks.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::Spawn).with_system(load_map_tracks.system()),
+            )
+            .add_system(play_music.system().after("apply_audio_settings"));
+    }
+}
+
+/// What's going on in the game, as far as the music is concerned.
+#[derive(Clone, Copy, Debug, Deserialize, Hash, PartialEq, Eq)]
+pub enum MusicMood {
+    Menu,
+    /// Waiting for the next wave to start.
+    BetweenWaves,
+    Wave,
+    BossWave,
+    Victory,
+    Defeat,
+}
+
+impl MusicMood {
+    /// The map property that can be used to replace this mood's track.
+    fn map_property(&self) -> &'static str {
+        match self {
+            MusicMood::Menu => "music_menu",
+            MusicMood::BetweenWaves => "music_between_waves",
+            MusicMood::Wave => "music_wave",
+            MusicMood::BossWave => "music_boss_wave",
+            MusicMood::Victory => "music_victory",
+            MusicMood::Defeat => "music_defeat",
+        }
+    }
+
+    /// Whether this mood's track starts over when it ends. The game over tracks are only
+    /// played once.
+    fn loops(&self) -> bool {
+        !matches!(self, MusicMood::Victory | MusicMood::Defeat)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MusicData {
+    /// Paths to the track for each mood. Moods without a track are silent.
+    #[serde(default)]
+    pub tracks: HashMap<MusicMood, String>,
+    /// How long it takes to fade from one track to the next, in seconds.
+    #[serde(default = "default_crossfade")]
+    pub crossfade: f32,
+}
+
+impl Default for MusicData {
+    fn default() -> Self {
+        Self {
+            tracks: HashMap::default(),
+            crossfade: default_crossfade(),
+        }
+    }
+}
+
+fn default_crossfade() -> f32 {
+    2.0
+}
+
+#[derive(Default)]
+struct MusicTracks {
+    /// The tracks from `game.ron`.
+    defaults: HashMap<MusicMood, Handle<AudioSource>>,
+    /// The tracks for the current map.
+    tracks: HashMap<MusicMood, Handle<AudioSource>>,
+}
+
+struct MusicPlayer {
+    channels: [AudioChannel; 2],
+    /// The channel that is fading in, or playing.
+    active: usize,
+    /// The volume of each channel relative to the music volume.
+    levels: [f32; 2],
+    /// Whether anything is playing in each channel.
+    playing: [bool; 2],
+    mood: Option<MusicMood>,
+}
+
+impl Default for MusicPlayer {
+    fn default() -> Self {
+        Self {
+            channels: [
+                AudioChannel::new("music_a".to_string()),
+                AudioChannel::new("music_b".to_string()),
+            ],
+            active: 0,
+            levels: [0.0, 0.0],
+            playing: [false, false],
+            mood: None,
+        }
+    }
+}
+
+fn load_tracks(
+    asset_server: Res<AssetServer>,
+    texture_handles: Res<TextureHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    mut music_tracks: ResMut<MusicTracks>,
+) {
+    let game_data = game_data_assets.get(&texture_handles.game_data).unwrap();
+
+    music_tracks.defaults = game_data
+        .music
+        .tracks
+        .iter()
+        .map(|(mood, path)| (*mood, asset_server.load(path.as_str())))
+        .collect();
+    music_tracks.tracks = music_tracks.defaults.clone();
+}
+
+fn load_map_tracks(
+    asset_server: Res<AssetServer>,
+    texture_handles: Res<TextureHandles>,
+    maps: Res<Assets<Map>>,
+    mut music_tracks: ResMut<MusicTracks>,
+) {
+    // Don't keep the tracks from a map that was played before.
+    music_tracks.tracks = music_tracks.defaults.clone();
+
+    let map = match maps.get(&texture_handles.tiled_map) {
+        Some(map) => map,
+        None => return,
+    };
+
+    for mood in &[
+        MusicMood::BetweenWaves,
+        MusicMood::Wave,
+        MusicMood::BossWave,
+        MusicMood::Victory,
+        MusicMood::Defeat,
+    ] {
+        if let Some(PropertyValue::StringValue(path)) = map.map.properties.get(mood.map_property())
+        {
+            music_tracks
+                .tracks
+                .insert(*mood, asset_server.load(path.as_str()));
+        }
+    }
+}
+
+fn current_mood(
+    state: &TaipoState,
+    game_state: &GameState,
+    waves: &Waves,
+    wave_state: &WaveState,
+    goal_query: &Query<&HitPoints, With<Goal>>,
+) -> Option<MusicMood> {
+    match state {
+        TaipoState::MainMenu | TaipoState::LoadMap => Some(MusicMood::Menu),
+        TaipoState::Spawn | TaipoState::Ready | TaipoState::GameOver | TaipoState::Paused => {
+            if game_state.over {
+                if game_state.loss_condition.is_met(goal_query.iter()) {
+                    Some(MusicMood::Defeat)
+                } else {
+                    Some(MusicMood::Victory)
+                }
+            } else if !game_state.ready {
+                Some(MusicMood::BetweenWaves)
+            } else {
+                match waves.waves.get(wave_state.current) {
+                    Some(wave) if wave_state.started && wave_state.delay_timer.finished() => {
+                        Some(if wave.boss {
+                            MusicMood::BossWave
+                        } else {
+                            MusicMood::Wave
+                        })
+                    }
+                    Some(_) => Some(MusicMood::BetweenWaves),
+                    // The last wave has spawned, but it's not over yet.
+                    None => Some(match waves.waves.last() {
+                        Some(wave) if wave.boss => MusicMood::BossWave,
+                        _ => MusicMood::Wave,
+                    }),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_music(
+    state: Res<State<TaipoState>>,
+    (game_state, waves, wave_state): (Res<GameState>, Res<Waves>, Res<WaveState>),
+    goal_query: Query<&HitPoints, With<Goal>>,
+    settings: Res<AudioSettings>,
+    music_tracks: Res<MusicTracks>,
+    mut player: ResMut<MusicPlayer>,
+    audio: Res<Audio>,
+    time: Res<Time>,
+    (texture_handles, game_data_assets): (Res<TextureHandles>, Res<Assets<GameData>>),
+) {
+    let mood = current_mood(
+        state.current(),
+        &game_state,
+        &waves,
+        &wave_state,
+        &goal_query,
+    );
+
+    if mood != player.mood {
+        info!("Music mood: {:?}", mood);
+
+        player.mood = mood;
+
+        // Switch to the other channel, so that whatever is playing fades out while the new
+        // track fades in.
+        let active = 1 - player.active;
+        player.active = active;
+
+        if player.playing[active] {
+            audio.stop_channel(&player.channels[active]);
+        }
+        player.levels[active] = 0.0;
+        player.playing[active] = false;
+
+        if let Some(mood) = mood {
+            if let Some(handle) = music_tracks.tracks.get(&mood) {
+                if mood.loops() {
+                    audio.play_looped_in_channel(handle.clone(), &player.channels[active]);
+                } else {
+                    audio.play_in_channel(handle.clone(), &player.channels[active]);
+                }
+                player.playing[active] = true;
+            }
+        }
+    }
+
+    let crossfade = game_data_assets
+        .get(&texture_handles.game_data)
+        .map_or(0.0, |game_data| game_data.music.crossfade);
+
+    let step = if crossfade > 0.0 {
+        time.delta_seconds() / crossfade
+    } else {
+        1.0
+    };
+
+    let mut levels_changed = false;
+
+    for i in 0..2 {
+        let goal = if i == player.active { 1.0 } else { 0.0 };
+        let level = player.levels[i];
+
+        let new_level = if level < goal {
+            (level + step).min(goal)
+        } else {
+            (level - step).max(goal)
+        };
+
+        if (new_level - level).abs() > f32::EPSILON {
+            player.levels[i] = new_level;
+            levels_changed = true;
+        }
+
+        if i != player.active && player.playing[i] && new_level <= 0.0 {
+            audio.stop_channel(&player.channels[i]);
+            player.playing[i] = false;
+        }
+    }
+
+    if levels_changed || settings.is_changed() {
+        for i in 0..2 {
+            audio.set_volume_in_channel(
+                settings.music_volume() * player.levels[i],
+                &player.channels[i],
+            );
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! The pause menu. While paused, the game's typing targets are hidden so that they can't be
+//! studied ahead of time, and only the menu's targets and command words can be typed.
+
+use bevy::prelude::*;
+
+use crate::{
+    audio::AudioSettings, layer, spawn_overlay_target, typing::TypingState,
+    util::set_visible_recursive, Action, ActionPanel, FontHandles, GameMarker, TaipoState,
+    TypingTarget, FONT_SIZE, FONT_SIZE_LABEL,
+};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HiddenTargets>()
+            .add_system_set(
+                SystemSet::on_enter(TaipoState::Paused)
+                    .with_system(pause_menu_startup.system())
+                    .with_system(hide_targets.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(TaipoState::Paused).with_system(update_settings_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(TaipoState::Paused)
+                    .with_system(pause_menu_cleanup.system())
+                    .with_system(show_targets.system()),
+            );
+    }
+}
+
+struct PauseMenuMarker;
+struct SettingsText;
+
+/// The typing targets that were hidden when the game was paused.
+#[derive(Default)]
+struct HiddenTargets(Vec<Entity>);
+
+fn settings_text(audio_settings: &AudioSettings, typing_state: &TypingState) -> String {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+
+    format!(
+        "volume {:.0}%  louder / quieter\nmute {}  auto {}  strict {}",
+        audio_settings.master * 100.0,
+        on_off(audio_settings.mute),
+        on_off(typing_state.auto_submit),
+        on_off(typing_state.auto_clear),
+    )
+}
+
+fn pause_menu_startup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    font_handles: Res<FontHandles>,
+    audio_settings: Res<AudioSettings>,
+    typing_state: Res<TypingState>,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, layer::OVERLAY_BG)),
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.7).into()),
+            sprite: Sprite::new(Vec2::new(280.0, 230.0)),
+            ..Default::default()
+        })
+        .insert(PauseMenuMarker)
+        .insert(GameMarker);
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 88.0, layer::OVERLAY)),
+            text: Text::with_section(
+                "ひとやすみ",
+                TextStyle {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(PauseMenuMarker)
+        .insert(GameMarker);
+
+    // "modoru" is "go back," and "yarinaosu" is "do over."
+    let choices = [
+        ("もどる", Action::Resume),
+        ("やりなおす", Action::Restart),
+        ("メニュー", Action::MainMenu),
+    ];
+
+    for (i, (word, action)) in choices.iter().enumerate() {
+        let entity = spawn_overlay_target(
+            word,
+            action.clone(),
+            Vec2::new(0.0, 48.0 - i as f32 * 30.0),
+            120.0,
+            &mut commands,
+            &mut materials,
+            &font_handles,
+        );
+        commands
+            .entity(entity)
+            .insert(PauseMenuMarker)
+            .insert(GameMarker);
+    }
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, -72.0, layer::OVERLAY)),
+            text: Text::with_section(
+                settings_text(&audio_settings, &typing_state),
+                TextStyle {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_LABEL,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(SettingsText)
+        .insert(PauseMenuMarker)
+        .insert(GameMarker);
+}
+
+/// Settings are changed with command words, which still work while paused.
+fn update_settings_text(
+    audio_settings: Res<AudioSettings>,
+    typing_state: Res<TypingState>,
+    mut query: Query<&mut Text, With<SettingsText>>,
+) {
+    if !audio_settings.is_changed() && !typing_state.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = settings_text(&audio_settings, &typing_state);
+    }
+}
+
+fn pause_menu_cleanup(mut commands: Commands, query: Query<Entity, With<PauseMenuMarker>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn hide_targets(
+    mut hidden: ResMut<HiddenTargets>,
+    mut target_query: Query<(Entity, &mut TypingTarget)>,
+    mut visible_query: Query<&mut Visible>,
+    children_query: Query<&Children>,
+) {
+    hidden.0.clear();
+
+    for (entity, mut target) in target_query.iter_mut() {
+        if target.fixed || target.disabled {
+            continue;
+        }
+
+        target.disabled = true;
+        set_visible_recursive(false, entity, &mut visible_query, &children_query);
+
+        hidden.0.push(entity);
+    }
+}
+
+fn show_targets(
+    mut hidden: ResMut<HiddenTargets>,
+    mut target_query: Query<&mut TypingTarget>,
+    mut visible_query: Query<&mut Visible>,
+    children_query: Query<&Children>,
+    mut action_panel: ResMut<ActionPanel>,
+) {
+    for entity in hidden.0.drain(..) {
+        if let Ok(mut target) = target_query.get_mut(entity) {
+            target.disabled = false;
+        }
+
+        set_visible_recursive(true, entity, &mut visible_query, &children_query);
+    }
+
+    // Prices and such may have changed while we were paused.
+    action_panel.update += 1;
+}
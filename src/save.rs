@@ -0,0 +1,71 @@
+//! Small bits of state that are kept between sessions, like audio settings. They're stored
+//! as RON in local storage on the web, or in files next to the game elsewhere.
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Returns the value saved under `key`, or `None` if there isn't one or it can't be read.
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let string = read(key)?;
+
+    match ron::de::from_str(&string) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring saved {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Saves `value` under `key`, logging a warning if that doesn't work out.
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let string = match ron::ser::to_string(value) {
+        Ok(string) => string,
+        Err(e) => {
+            warn!("Failed to serialize {}: {}", key, e);
+            return;
+        }
+    };
+
+    if let Err(e) = write(key, &string) {
+        warn!("Failed to save {}: {}", key, e);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read(key: &str) -> Option<String> {
+    storage()?.get_item(&format!("taipo.{}", key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(key: &str, value: &str) -> anyhow::Result<()> {
+    storage()
+        .ok_or_else(|| anyhow!("local storage is unavailable"))?
+        .set_item(&format!("taipo.{}", key), value)
+        .map_err(|e| anyhow!("{:?}", e))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(key: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from("save").join(format!("{}.ron", key))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read(key: &str) -> Option<String> {
+    std::fs::read_to_string(path(key)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(key: &str, value: &str) -> anyhow::Result<()> {
+    let path = path(key);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, value)?;
+    Ok(())
+}
@@ -1,12 +1,18 @@
 use bevy::{
     input::{keyboard::KeyCode, keyboard::KeyboardInput},
     prelude::*,
+    window::ReceivedCharacter,
 };
-use bevy_kira_audio::Audio;
+use rand::{prelude::SliceRandom, thread_rng};
 
-use crate::{AudioHandles, AudioSettings, FontHandles, TaipoState, FONT_SIZE_INPUT};
+use crate::{
+    audio::GameplayEvent,
+    bindings::{ActionEvent, Bindings},
+    kana, Action, FontHandles, GameMarker, TaipoState, TextureHandles, FONT_SIZE_INPUT,
+};
 
-use std::collections::VecDeque;
+use bevy::utils::HashMap;
+use std::{borrow::Cow, collections::VecDeque};
 
 pub struct TypingPlugin;
 
@@ -17,15 +23,18 @@ impl Plugin for TypingPlugin {
             .insert_resource(TypingCursorTimer(Timer::from_seconds(0.5, true)))
             .insert_resource(TypingState::default())
             .init_resource::<TypingTargets>()
+            .init_resource::<TypingStats>()
             .add_event::<AsciiModeEvent>()
             .add_event::<TypingTargetFinishedEvent>()
             .add_event::<TypingSubmitEvent>()
+            .add_event::<ActionEvent>()
             .add_system(ascii_mode_event.system().before("keyboard"))
-            .add_system(submit_event.system().before("keyboard"))
+            .add_system(submit_event.system().label("submit").before("keyboard"))
+            .add_system(record_stats.system().after("submit").before("keyboard"))
             .add_system(keyboard.system().label("keyboard"))
             .add_system(update_target_text.system().after("keyboard"))
             .add_system(update_buffer_text.system().after("keyboard"))
-            .add_system(audio.system().after("keyboard"))
+            .add_system(audio.system().after("keyboard").before("play_sfx"))
             .add_system(update_cursor_text.system());
     }
 }
@@ -35,16 +44,191 @@ pub struct TypingTargetContainer;
 #[derive(Clone, Debug, Default)]
 pub struct TypingTarget {
     pub render: Vec<String>,
-    pub ascii: Vec<String>,
+    /// The accepted inputs for each glyph in `render`. The first one is what gets
+    /// displayed in ascii mode.
+    pub ascii: Vec<Vec<String>>,
     pub fixed: bool,
     pub disabled: bool,
+    /// Shown in muted colors, for actions the player can't afford.
+    pub dimmed: bool,
+}
+
+/// How far some input got into a `TypingTarget`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypingMatch {
+    /// Which of the accepted inputs was typed for each fully typed glyph.
+    pub typed: Vec<usize>,
+    /// How many bytes of input have been typed towards the glyph after those.
+    pub partial: usize,
+}
+
+/// Word lists leave out words that can be typed more ways than this, which is plenty for any
+/// sensible word, so that checking targets against each other doesn't take forever.
+pub const MAX_INPUTS: usize = 256;
+
+impl TypingTarget {
+    /// Returns the input that gets displayed in ascii mode.
+    pub fn displayed_ascii(&self) -> String {
+        self.ascii
+            .iter()
+            .filter_map(|inputs| inputs.first())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the accepted inputs for each glyph when typing in the given mode.
+    fn glyph_inputs(&self, mode: InputMode) -> Cow<[Vec<String>]> {
+        match mode {
+            InputMode::Romaji => Cow::Borrowed(&self.ascii),
+            // Kana keyboard layouts type hiragana, so that's fine for katakana too.
+            InputMode::Kana => Cow::Owned(
+                self.render
+                    .iter()
+                    .map(|render| {
+                        let hiragana = kana::to_hiragana_str(render);
+                        if hiragana == *render {
+                            vec![render.clone()]
+                        } else {
+                            vec![render.clone(), hiragana]
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns how far into this target `input` got, or `None` if `input` isn't a prefix of
+    /// any of the accepted ways of typing it.
+    pub fn match_input(&self, input: &str, mode: InputMode) -> Option<TypingMatch> {
+        match_glyphs(&self.glyph_inputs(mode), input)
+    }
+
+    /// Returns the match for the longest prefix of `input` that is valid for this target.
+    pub fn longest_match(&self, input: &str, mode: InputMode) -> TypingMatch {
+        let glyphs = self.glyph_inputs(mode);
+
+        input
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(input.len()))
+            .rev()
+            .find_map(|i| match_glyphs(&glyphs, &input[..i]))
+            .unwrap_or_default()
+    }
+
+    /// Returns true if `input` is one of the accepted ways of typing this whole target.
+    pub fn is_match(&self, input: &str, mode: InputMode) -> bool {
+        matches!(
+            self.match_input(input, mode),
+            Some(m) if m.typed.len() == self.render.len() && m.partial == 0
+        )
+    }
+
+    /// Returns the number of ways of typing this whole target in romaji.
+    pub fn input_count(&self) -> usize {
+        self.ascii
+            .iter()
+            .fold(1, |count, inputs| count.saturating_mul(inputs.len()))
+    }
+
+    /// Returns every accepted way of typing this whole target in romaji.
+    pub fn inputs(&self) -> Vec<String> {
+        self.inputs_for(InputMode::Romaji)
+    }
+
+    /// Returns every accepted way of typing this whole target in the given mode.
+    pub fn inputs_for(&self, mode: InputMode) -> Vec<String> {
+        let mut inputs = vec![String::new()];
+
+        for glyph in self.glyph_inputs(mode).iter() {
+            inputs = inputs
+                .iter()
+                .flat_map(|prefix| glyph.iter().map(move |a| format!("{}{}", prefix, a)))
+                .collect();
+        }
+
+        inputs
+    }
+}
+
+fn match_glyphs(glyphs: &[Vec<String>], input: &str) -> Option<TypingMatch> {
+    if input.is_empty() {
+        return Some(TypingMatch::default());
+    }
+
+    let (first, rest) = glyphs.split_first()?;
+
+    // Some inputs can be split up more than one way, like the `n` in `konnichiha`, so try
+    // all of them and keep whichever gets furthest.
+
+    let mut best: Option<TypingMatch> = None;
+
+    for (i, alternative) in first.iter().enumerate() {
+        let candidate = if let Some(leftover) = input.strip_prefix(alternative.as_str()) {
+            match_glyphs(rest, leftover).map(|mut m| {
+                m.typed.insert(0, i);
+                m
+            })
+        } else if alternative.starts_with(input) {
+            Some(TypingMatch {
+                typed: vec![],
+                partial: input.len(),
+            })
+        } else {
+            None
+        };
+
+        if let Some(candidate) = candidate {
+            if best
+                .as_ref()
+                .map_or(true, |b| candidate.typed.len() > b.typed.len())
+            {
+                best = Some(candidate);
+            }
+        }
+    }
+
+    best
 }
 pub struct TypingTargetImage;
 pub struct TypingTargetPriceContainer;
 pub struct TypingTargetPriceText;
 pub struct TypingTargetPriceImage;
+/// The text of a typing target. It has three sections: the glyphs that have been typed, the
+/// glyph that is partially typed and the rest.
 pub struct TypingTargetText;
 
+const MATCHED_COLOR: Color = Color::GREEN;
+const PARTIAL_COLOR: Color = Color::rgb(1.0, 1.0, 0.0);
+const UNMATCHED_COLOR: Color = Color::WHITE;
+/// For the typed part of a target once the buffer has gone past it.
+const OVERTYPED_COLOR: Color = Color::rgb(1.0, 0.5, 0.0);
+const DIMMED_MATCHED_COLOR: Color = Color::RED;
+const DIMMED_UNMATCHED_COLOR: Color = Color::GRAY;
+
+/// Returns the sections for a new `TypingTargetText` showing `text`.
+pub fn typing_target_text_sections(
+    text: String,
+    font: Handle<Font>,
+    font_size: f32,
+) -> Vec<TextSection> {
+    [
+        ("".to_string(), MATCHED_COLOR),
+        ("".to_string(), PARTIAL_COLOR),
+        (text, UNMATCHED_COLOR),
+    ]
+    .iter()
+    .map(|(value, color)| TextSection {
+        value: value.clone(),
+        style: TextStyle {
+            font: font.clone(),
+            font_size,
+            color: *color,
+        },
+    })
+    .collect()
+}
+
 struct TypingBuffer;
 struct TypingCursor;
 struct TypingCursorTimer(Timer);
@@ -63,54 +247,320 @@ pub struct TypingTargetFinishedEvent {
     pub target: TypingTarget,
 }
 
+/// What the player types to match a target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputMode {
+    /// The romaji in `TypingTarget::ascii`.
+    Romaji,
+    /// The rendered text itself, with an IME or a kana keyboard layout.
+    Kana,
+}
+
+impl Default for InputMode {
+    fn default() -> Self {
+        InputMode::Romaji
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct TypingState {
     buf: String,
     pub ascii_mode: bool,
+    pub input_mode: InputMode,
+    /// Submit the buffer as soon as it matches a target, without waiting for enter.
+    pub auto_submit: bool,
+    /// Clear the buffer as soon as it stops matching any target.
+    pub auto_clear: bool,
+    /// When set, typed characters that aren't in here are ignored. This only applies to
+    /// `InputMode::Romaji`.
+    pub accepted_chars: Option<String>,
     just_typed_char: bool,
+    /// Whether the last character typed didn't match any target.
+    just_mistyped: bool,
+}
+
+/// Statistics about the player's typing during the current game.
+#[derive(Default, Debug)]
+pub struct TypingStats {
+    /// Characters typed, plus presses of backspace and enter.
+    pub keystrokes: u32,
+    /// Typed characters that kept the buffer on track for some target.
+    pub correct_chars: u32,
+    /// Typed characters that didn't.
+    pub wrong_chars: u32,
+    pub backspaces: u32,
+    /// Submitted words that didn't match any target.
+    pub rejected: u32,
+    pub completed: Vec<CompletedTarget>,
+    /// How many times a wrong character was typed on the way to each glyph.
+    pub glyph_errors: HashMap<String, u32>,
+    /// Seconds of play so far, not counting time spent paused or on other screens.
+    playing_seconds: f64,
+    /// When the first character of the current buffer was typed, in `playing_seconds`.
+    target_started: Option<f64>,
+    /// Wrong characters typed since then.
+    target_wrong_chars: u32,
+    last_submit: Option<Submission>,
+}
+
+/// The last word submitted with enter.
+#[derive(Clone, Copy, Debug, Default)]
+struct Submission {
+    seconds: f32,
+    chars: usize,
+    wrong_chars: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompletedTarget {
+    pub render: String,
+    /// Seconds from typing the first character to pressing enter.
+    pub seconds: f32,
+    /// Characters in the submitted word.
+    pub chars: usize,
+    /// Wrong characters typed along the way, even if they were deleted.
+    pub wrong_chars: u32,
+}
+
+impl CompletedTarget {
+    /// Whether the target was typed quickly and without mistakes.
+    pub fn is_good(&self) -> bool {
+        self.wrong_chars == 0 && self.seconds <= self.chars as f32 * GOOD_SECONDS_PER_CHAR
+    }
+}
+
+impl TypingStats {
+    /// Returns the percentage of typed characters that were correct.
+    pub fn accuracy(&self) -> f32 {
+        let total = self.correct_chars + self.wrong_chars;
+        if total == 0 {
+            return 100.0;
+        }
+
+        self.correct_chars as f32 / total as f32 * 100.0
+    }
+
+    /// Returns words per minute, counting five characters as a word, over the time spent
+    /// typing targets that were completed.
+    pub fn wpm(&self) -> f32 {
+        let seconds: f32 = self.completed.iter().map(|c| c.seconds).sum();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+
+        let chars: usize = self.completed.iter().map(|c| c.chars).sum();
+
+        chars as f32 / 5.0 / (seconds / 60.0)
+    }
+
+    /// Returns up to `count` of the glyphs with the most errors, worst first.
+    pub fn most_missed(&self, count: usize) -> Vec<(&str, u32)> {
+        let mut errors: Vec<(&str, u32)> = self
+            .glyph_errors
+            .iter()
+            .map(|(glyph, errors)| (glyph.as_str(), *errors))
+            .collect();
+
+        errors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        errors.truncate(count);
+
+        errors
+    }
 }
 
+/// Targets typed in less time than this per character, without mistakes, count as
+/// well known.
+const GOOD_SECONDS_PER_CHAR: f32 = 0.75;
+
+/// The number of Leitner boxes. Targets move up a box each time they are typed well and
+/// back to the first box otherwise, and each box comes up half as often as the one
+/// before it.
+const LEITNER_BOXES: usize = 5;
+
 #[derive(Default)]
 pub struct TypingTargets {
-    pub possible: VecDeque<TypingTarget>,
-    used_ascii: Vec<Vec<String>>,
+    /// The mode that targets are typed in, which decides what counts as ambiguous.
+    input_mode: InputMode,
+    /// Targets that can be picked, along with their inputs in `input_mode`.
+    possible: VecDeque<(TypingTarget, Vec<String>)>,
+    /// Targets that are currently on screen, along with their inputs in `input_mode`.
+    used: Vec<(TypingTarget, Vec<String>)>,
+    /// The inputs in `input_mode` of targets that can always be typed, like command words.
+    reserved: Vec<Vec<String>>,
+    /// The Leitner box of every target that has been typed, by rendered text.
+    boxes: HashMap<String, usize>,
+}
+
+/// Whether a way of typing one target is also a way of typing the other.
+fn is_ambiguous(a: &[String], b: &[String]) -> bool {
+    a.iter().any(|a| b.contains(a))
+}
+
+/// Whether a way of typing one target is the start of a way of typing the other, so that
+/// typing one would be taken as progress on the other.
+fn is_prefix_ambiguous(a: &[String], b: &[String]) -> bool {
+    a.iter().any(|a| {
+        b.iter()
+            .any(|b| a.starts_with(b.as_str()) || b.starts_with(a.as_str()))
+    })
 }
 
 impl TypingTargets {
+    /// Forgets the possible, used and reserved targets, but keeps the Leitner boxes so
+    /// that the player's progress carries over to the next game.
+    pub fn clear(&mut self) {
+        self.possible.clear();
+        self.used.clear();
+        self.reserved.clear();
+    }
+
+    /// Replaces the targets that can be picked, which will be typed in `input_mode`.
+    pub fn set_possible(&mut self, targets: Vec<TypingTarget>, input_mode: InputMode) {
+        self.input_mode = input_mode;
+        self.possible = targets
+            .into_iter()
+            .map(|target| {
+                let inputs = target.inputs_for(input_mode);
+                (target, inputs)
+            })
+            .collect();
+    }
+
+    /// Keeps targets that are picked from now on from being confused with `target`, which
+    /// can be typed at any time in the input mode given to `set_possible`.
+    pub fn reserve(&mut self, target: &TypingTarget) {
+        self.reserved.push(target.inputs_for(self.input_mode));
+    }
+
     /// Returns the next `TypingTarget`, removing it from the list of possible
-    /// targets and ensuring that it is not ambiguous with another target that
-    /// was previous removed from the stack.
+    /// targets and ensuring that none of the ways of typing it are a prefix of
+    /// the ways of typing a target that was previously removed from the stack,
+    /// or the other way around.
+    ///
+    /// Targets that the player doesn't know well yet are more likely to be
+    /// picked.
+    ///
+    /// If that isn't possible, we settle for a target that is only a prefix of
+    /// another, then for any target at all, and finally for reusing one that is
+    /// already on screen.
     pub fn pop_front(&mut self) -> TypingTarget {
-        let next_pos = self
+        let next_pos = self.pick(is_prefix_ambiguous).or_else(|| {
+            warn!("Not enough typing targets that don't share a prefix with the ones in use.");
+            self.pick(is_ambiguous)
+        });
+
+        let next_pos = next_pos.or_else(|| {
+            warn!("Not enough typing targets that can't be confused with the ones in use.");
+            self.pick(|_, _| false)
+        });
+
+        let (next, inputs) = match next_pos {
+            Some(pos) => self.possible.remove(pos).unwrap(),
+            None => match self.used.first() {
+                Some(used) => {
+                    warn!("Ran out of typing targets, reusing one that is already in use.");
+                    used.clone()
+                }
+                None => {
+                    error!("There are no typing targets at all.");
+                    let target = TypingTarget {
+                        render: vec!["?".to_string()],
+                        ascii: vec![vec!["?".to_string()]],
+                        ..Default::default()
+                    };
+                    let inputs = target.inputs_for(self.input_mode);
+                    (target, inputs)
+                }
+            },
+        };
+
+        self.used.push((next.clone(), inputs));
+
+        next
+    }
+
+    /// Picks a random possible target that doesn't `conflict` with any of the
+    /// targets in use or reserved, favouring the ones in lower Leitner boxes.
+    fn pick(&self, conflict: impl Fn(&[String], &[String]) -> bool) -> Option<usize> {
+        let candidates: Vec<(usize, u32)> = self
             .possible
             .iter()
-            .position(|v| !self.used_ascii.iter().any(|ascii| *ascii == v.ascii))
-            .expect("no word found");
+            .enumerate()
+            .filter(|(_, (_, inputs))| !self.conflicts(inputs, &conflict))
+            .map(|(i, (v, _))| {
+                let leitner_box = self.boxes.get(&v.render.join("")).copied().unwrap_or(0);
+                (i, 1 << (LEITNER_BOXES - 1 - leitner_box))
+            })
+            .collect();
 
-        let next = self.possible.remove(next_pos).unwrap();
+        candidates
+            .choose_weighted(&mut thread_rng(), |(_, weight)| *weight)
+            .ok()
+            .map(|(i, _)| *i)
+    }
 
-        self.used_ascii.push(next.ascii.clone());
+    /// Returns the first possible target that doesn't share a prefix with any of the
+    /// targets in use or reserved, removing it from the list of possible targets. Unlike
+    /// `pop_front`, this never settles for an ambiguous target.
+    pub fn pop_unambiguous(&mut self) -> Option<TypingTarget> {
+        let pos = self
+            .possible
+            .iter()
+            .position(|(_, inputs)| !self.conflicts(inputs, is_prefix_ambiguous))?;
 
-        next
+        let (next, inputs) = self.possible.remove(pos).unwrap();
+        self.used.push((next.clone(), inputs));
+
+        Some(next)
+    }
+
+    /// Whether `inputs` `conflict` with those of any of the targets in use or reserved.
+    fn conflicts(
+        &self,
+        inputs: &[String],
+        conflict: impl Fn(&[String], &[String]) -> bool,
+    ) -> bool {
+        self.used
+            .iter()
+            .map(|(_, used_inputs)| used_inputs)
+            .chain(self.reserved.iter())
+            .any(|used_inputs| conflict(inputs, used_inputs))
+    }
+
+    /// Moves a target that was just typed to the next Leitner box if it was typed well, or
+    /// back to the first one if not.
+    pub fn record(&mut self, target: &TypingTarget, good: bool) {
+        let leitner_box = self.boxes.entry(target.render.join("")).or_insert(0);
+
+        *leitner_box = if good {
+            (*leitner_box + 1).min(LEITNER_BOXES - 1)
+        } else {
+            0
+        };
     }
 
     /// Puts a `TypingTarget` back into the list of possible targets and returns
     /// the next target, ensuring that it is not ambiguous with another target
-    // that was previously removed from the stack or the target that was put
-    // back.
+    /// that is still in use or the target that was put back.
     pub fn push_back_pop_front(&mut self, target: TypingTarget) -> TypingTarget {
-        self.possible.push_back(target.clone());
+        let inputs = target.inputs_for(self.input_mode);
+        self.possible.push_back((target.clone(), inputs));
 
         let next = self.pop_front();
 
-        if next.ascii != target.ascii {
-            self.used_ascii.retain(|ascii| *ascii != target.ascii);
+        if let Some(pos) = self
+            .used
+            .iter()
+            .position(|(used, _)| used.render == target.render && used.ascii == target.ascii)
+        {
+            self.used.remove(pos);
         }
 
         next
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn submit_event(
     mut typing_submit_events: EventReader<TypingSubmitEvent>,
     mut typing_target_finished_events: EventWriter<TypingTargetFinishedEvent>,
@@ -119,17 +569,23 @@ fn submit_event(
     mut text_query: Query<&mut Text, With<TypingTargetText>>,
     typing_state: Res<TypingState>,
     mut typing_targets: ResMut<TypingTargets>,
+    mut typing_stats: ResMut<TypingStats>,
+    mut gameplay_events: EventWriter<GameplayEvent>,
 ) {
     for event in typing_submit_events.iter() {
+        let mut matched = false;
+
         for (entity, mut target) in query.iter_mut() {
             if target.disabled {
                 continue;
             }
 
-            if target.ascii.join("") != event.text {
+            if !target.is_match(&event.text, typing_state.input_mode) {
                 continue;
             }
 
+            matched = true;
+
             typing_target_finished_events.send(TypingTargetFinishedEvent {
                 entity,
                 target: target.clone(),
@@ -145,8 +601,9 @@ fn submit_event(
                 for child in children.iter() {
                     if let Ok(mut text) = text_query.get_mut(*child) {
                         text.sections[0].value = "".to_string();
-                        text.sections[1].value = if typing_state.ascii_mode {
-                            new_target.ascii.join("")
+                        text.sections[1].value = "".to_string();
+                        text.sections[2].value = if typing_state.ascii_mode {
+                            new_target.displayed_ascii()
                         } else {
                             new_target.render.join("")
                         };
@@ -157,6 +614,38 @@ fn submit_event(
             target.ascii = new_target.ascii.clone();
             target.render = new_target.render.clone();
         }
+
+        if matched {
+            gameplay_events.send(GameplayEvent::TargetCompleted);
+        } else {
+            typing_stats.rejected += 1;
+            gameplay_events.send(GameplayEvent::TargetRejected);
+        }
+    }
+}
+
+fn record_stats(
+    mut typing_target_finished_events: EventReader<TypingTargetFinishedEvent>,
+    mut typing_stats: ResMut<TypingStats>,
+    mut typing_targets: ResMut<TypingTargets>,
+) {
+    for event in typing_target_finished_events.iter() {
+        // Commands like "help" aren't really typing practice.
+        if event.target.fixed {
+            continue;
+        }
+
+        let submission = typing_stats.last_submit.unwrap_or_default();
+
+        let completed = CompletedTarget {
+            render: event.target.render.join(""),
+            seconds: submission.seconds,
+            chars: submission.chars,
+            wrong_chars: submission.wrong_chars,
+        };
+
+        typing_targets.record(&event.target, completed.is_good());
+        typing_stats.completed.push(completed);
     }
 }
 
@@ -176,7 +665,12 @@ fn startup(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
     font_handles: Res<FontHandles>,
+    mut typing_stats: ResMut<TypingStats>,
+    mut typing_state: ResMut<TypingState>,
 ) {
+    *typing_stats = TypingStats::default();
+    typing_state.buf.clear();
+
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -195,6 +689,7 @@ fn startup(
             ..Default::default()
         })
         .insert(TypingTargetPriceContainer)
+        .insert(GameMarker)
         .with_children(|parent| {
             parent.spawn_bundle(TextBundle {
                 style: Style {
@@ -253,35 +748,13 @@ fn startup(
         });
 }
 
-fn audio(
-    state: Res<TypingState>,
-    query: Query<&TypingTarget>,
-    audio: Res<Audio>,
-    audio_handles: Res<AudioHandles>,
-    audio_settings: Res<AudioSettings>,
-) {
+fn audio(state: Res<TypingState>, mut gameplay_events: EventWriter<GameplayEvent>) {
     if !state.is_changed() {
         return;
     }
 
-    let mut longest: usize = 0;
-
-    for target in query.iter().filter(|t| !t.disabled) {
-        let matched_length = if target.ascii.join("").starts_with(&state.buf) {
-            state.buf.len()
-        } else {
-            0
-        };
-
-        if matched_length > longest {
-            longest = matched_length;
-        }
-    }
-
-    if !audio_settings.mute && state.just_typed_char {
-        if longest < state.buf.len() {
-            audio.play(audio_handles.wrong_character.clone());
-        }
+    if state.just_typed_char && state.just_mistyped {
+        gameplay_events.send(GameplayEvent::WrongCharacter);
     }
 }
 
@@ -295,49 +768,71 @@ fn update_target_text(
         Query<&Text, With<TypingTargetText>>,
         Query<&mut Text, With<TypingTargetText>>,
     )>,
-    query: Query<(&TypingTarget, &Children)>,
+    query: Query<(Entity, &TypingTarget, &Children)>,
+    changed_query: Query<Entity, Changed<TypingTarget>>,
 ) {
-    if !state.is_changed() {
-        return;
-    }
-
-    info!("changedres<typingstate>");
-
-    for (target, target_children) in query.iter() {
+    for (entity, target, target_children) in query.iter() {
         if target.disabled {
             continue;
         }
 
-        let mut matched = "".to_string();
-        let mut unmatched = "".to_string();
-        let mut buf = state.buf.clone();
-        let mut fail = false;
+        if !state.is_changed() && changed_query.get(entity).is_err() {
+            continue;
+        }
 
-        let render_iter = if state.ascii_mode {
-            target.ascii.iter()
-        } else {
-            target.render.iter()
-        };
+        let full_match = target.match_input(&state.buf, state.input_mode);
+        let overtyped = full_match.is_none();
+        let typing_match =
+            full_match.unwrap_or_else(|| target.longest_match(&state.buf, state.input_mode));
+        let typed = typing_match.typed;
 
-        for (ascii, render) in target.ascii.iter().zip(render_iter) {
-            match (fail, buf.strip_prefix(ascii)) {
-                (false, Some(leftover)) => {
-                    matched.push_str(&render);
-                    buf = leftover.to_string().clone();
-                }
-                (true, _) | (_, None) => {
-                    fail = true;
-                    unmatched.push_str(&render);
-                }
-            }
+        let mut values = ["".to_string(), "".to_string(), "".to_string()];
+
+        for (i, (ascii, render)) in target.ascii.iter().zip(target.render.iter()).enumerate() {
+            // In ascii mode, show whichever romanization was actually typed.
+            let glyph = match (state.ascii_mode, state.input_mode, typed.get(i)) {
+                (true, InputMode::Romaji, Some(alternative)) => &ascii[*alternative],
+                (true, _, _) => &ascii[0],
+                (false, _, _) => render,
+            };
+
+            let section = if i < typed.len() {
+                0
+            } else if i == typed.len() && typing_match.partial > 0 {
+                1
+            } else {
+                2
+            };
+
+            values[section].push_str(glyph);
         }
 
+        let (matched_color, partial_color) = if overtyped {
+            (OVERTYPED_COLOR, OVERTYPED_COLOR)
+        } else if target.dimmed {
+            (DIMMED_MATCHED_COLOR, DIMMED_MATCHED_COLOR)
+        } else {
+            (MATCHED_COLOR, PARTIAL_COLOR)
+        };
+        let unmatched_color = if target.dimmed {
+            DIMMED_UNMATCHED_COLOR
+        } else {
+            UNMATCHED_COLOR
+        };
+        let colors = [matched_color, partial_color, unmatched_color];
+
         for child in target_children.iter() {
             if let Ok(text) = text_queries.q0().get(*child) {
-                if text.sections[0].value != matched || text.sections[1].value != unmatched {
+                let unchanged = text.sections.iter().enumerate().all(|(i, section)| {
+                    section.value == values[i] && section.style.color == colors[i]
+                });
+
+                if !unchanged {
                     if let Ok(mut textmut) = text_queries.q1_mut().get_mut(*child) {
-                        textmut.sections[0].value = matched.clone();
-                        textmut.sections[1].value = unmatched.clone();
+                        for (i, section) in textmut.sections.iter_mut().enumerate() {
+                            section.value = values[i].clone();
+                            section.style.color = colors[i];
+                        }
                     }
                 }
             }
@@ -373,78 +868,412 @@ fn update_cursor_text(
     }
 }
 
+fn push_char(
+    typing_state: &mut TypingState,
+    typing_stats: &mut TypingStats,
+    targets: &Query<&TypingTarget>,
+    c: char,
+    now: f64,
+) {
+    let mode = typing_state.input_mode;
+
+    // Uppercase letters are typed as lowercase when only lowercase ones are accepted, so
+    // that shift or caps lock doesn't swallow them.
+    let c = match (mode, &typing_state.accepted_chars) {
+        (InputMode::Romaji, Some(accepted_chars)) if !accepted_chars.contains(c) => {
+            c.to_ascii_lowercase()
+        }
+        _ => c,
+    };
+
+    let accepted = match (mode, &typing_state.accepted_chars) {
+        (InputMode::Romaji, Some(accepted_chars)) => accepted_chars.contains(c),
+        _ => true,
+    };
+
+    typing_state.just_typed_char = accepted;
+
+    if !accepted {
+        return;
+    }
+
+    if typing_state.buf.is_empty() {
+        typing_stats.target_started = Some(now);
+        typing_stats.target_wrong_chars = 0;
+    }
+
+    let before = typing_state.buf.clone();
+    typing_state.buf.push(c);
+    typing_stats.keystrokes += 1;
+
+    let active = || targets.iter().filter(|t| !t.disabled);
+
+    typing_state.just_mistyped =
+        !active().any(|t| t.match_input(&typing_state.buf, mode).is_some());
+
+    if !typing_state.just_mistyped {
+        typing_stats.correct_chars += 1;
+        return;
+    }
+
+    typing_stats.wrong_chars += 1;
+    typing_stats.target_wrong_chars += 1;
+
+    if typing_state.auto_clear {
+        typing_state.buf.clear();
+    }
+
+    // Blame the glyphs that the player was on their way to typing, unless they hadn't
+    // started on anything yet.
+
+    if before.is_empty() {
+        return;
+    }
+
+    let mut glyphs: Vec<&String> = vec![];
+
+    for target in active() {
+        if let Some(typing_match) = target.match_input(&before, mode) {
+            if let Some(glyph) = target.render.get(typing_match.typed.len()) {
+                if !glyphs.contains(&glyph) {
+                    glyphs.push(glyph);
+                }
+            }
+        }
+    }
+
+    for glyph in glyphs {
+        *typing_stats.glyph_errors.entry(glyph.clone()).or_insert(0) += 1;
+    }
+}
+
+/// Returns the length that `Action::DeleteWord` should cut `buf` back to. If the end of
+/// `buf` doesn't match any target, that's the part that gets deleted. Otherwise, it's the
+/// last glyph, or the partially typed one.
+fn delete_word_len(buf: &str, mode: InputMode, targets: &Query<&TypingTarget>) -> usize {
+    let mut valid = 0;
+    let mut boundary = 0;
+
+    for target in targets.iter().filter(|t| !t.disabled) {
+        let glyphs = target.glyph_inputs(mode);
+        let typing_match = target.longest_match(buf, mode);
+
+        let typed_lens: Vec<usize> = typing_match
+            .typed
+            .iter()
+            .enumerate()
+            .map(|(i, alternative)| glyphs[i][*alternative].len())
+            .collect();
+
+        let len = typed_lens.iter().sum::<usize>() + typing_match.partial;
+        let chunk_start = if typing_match.partial > 0 {
+            len - typing_match.partial
+        } else {
+            len - typed_lens.last().copied().unwrap_or(0)
+        };
+
+        if len > valid {
+            valid = len;
+            boundary = chunk_start;
+        } else if len == valid {
+            boundary = boundary.max(chunk_start);
+        }
+    }
+
+    if valid < buf.len() {
+        valid
+    } else {
+        boundary
+    }
+}
+
+fn submit(
+    typing_state: &mut TypingState,
+    typing_stats: &mut TypingStats,
+    typing_submit_events: &mut EventWriter<TypingSubmitEvent>,
+    now: f64,
+) {
+    let text = typing_state.buf.clone();
+
+    let wrong_chars = typing_stats.target_wrong_chars;
+    typing_stats.last_submit = typing_stats
+        .target_started
+        .take()
+        .map(|started| Submission {
+            seconds: (now - started) as f32,
+            chars: text.chars().count(),
+            wrong_chars,
+        });
+
+    typing_state.buf.clear();
+    typing_state.just_typed_char = false;
+    typing_submit_events.send(TypingSubmitEvent { text });
+}
+
+/// Submits the buffer if auto-submit is on and the buffer is exactly one of the ways of
+/// typing a single target. We hold off if the buffer is also the start of another way of
+/// typing something, like `hon` on the way to `honn`.
+fn auto_submit(
+    typing_state: &mut TypingState,
+    typing_stats: &mut TypingStats,
+    typing_submit_events: &mut EventWriter<TypingSubmitEvent>,
+    targets: &Query<&TypingTarget>,
+    now: f64,
+) {
+    if !typing_state.auto_submit || typing_state.buf.is_empty() {
+        return;
+    }
+
+    let mut matched = 0;
+
+    for target in targets.iter().filter(|t| !t.disabled) {
+        let mut target_matched = false;
+
+        for input in target.inputs_for(typing_state.input_mode) {
+            if input == typing_state.buf {
+                target_matched = true;
+            } else if input.starts_with(&typing_state.buf) {
+                return;
+            }
+        }
+
+        if target_matched {
+            matched += 1;
+        }
+    }
+
+    if matched == 1 {
+        submit(typing_state, typing_stats, typing_submit_events, now);
+    }
+}
+
+/// Whether pressing `key` might produce a `ReceivedCharacter`.
+fn is_character_key(key: KeyCode) -> bool {
+    use KeyCode::*;
+
+    !matches!(
+        key,
+        Back | Return
+            | Escape
+            | Tab
+            | LShift
+            | RShift
+            | LControl
+            | RControl
+            | LAlt
+            | RAlt
+            | LWin
+            | RWin
+            | Capital
+            | Numlock
+            | Scroll
+            | Left
+            | Right
+            | Up
+            | Down
+            | Home
+            | End
+            | PageUp
+            | PageDown
+            | Insert
+            | Delete
+            | Snapshot
+            | Pause
+            | Apps
+            | F1
+            | F2
+            | F3
+            | F4
+            | F5
+            | F6
+            | F7
+            | F8
+            | F9
+            | F10
+            | F11
+            | F12
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
 fn keyboard(
     mut typing_state: ResMut<TypingState>,
     mut typing_submit_events: EventWriter<TypingSubmitEvent>,
+    mut action_events: EventWriter<ActionEvent>,
     mut keyboard_input_events: EventReader<KeyboardInput>,
+    mut received_character_events: EventReader<ReceivedCharacter>,
+    mut typing_stats: ResMut<TypingStats>,
+    targets: Query<&TypingTarget>,
+    (time, keyboard_input, bindings_assets, texture_handles, state): (
+        Res<Time>,
+        Res<Input<KeyCode>>,
+        Res<Assets<Bindings>>,
+        Res<TextureHandles>,
+        Res<State<TaipoState>>,
+    ),
 ) {
-    // We were previously using Res<Events<ReceivedCharacter>> to handle the ascii bits,
-    // and Res<Events<KeyboardInput>> to handle backspace/enter, but there was something
-    // wacky going on where backspace could end up coming in out of order.
+    // Characters come from ReceivedCharacter so that the keyboard layout, shift and dead
+    // keys are all taken into account, but we never get backspace/enter from it on the
+    // web, so those still come from KeyboardInput by way of the key bindings.
     //
-    // After testing using puppeteer to shove various keyboard inputs in, it seems like
-    // this solution, though ugly, results in a better typing experience.
+    // The two kinds of event arrive in separate queues, and we used to see backspace
+    // being handled out of order with the characters around it. To avoid that, we walk
+    // through the key presses in order and give each key that might produce a character
+    // the next received character. Anything left over (from a dead key, for instance)
+    // goes at the end.
     //
-    // I had also attempted to get ReceivedCharacter to give me backspace/enter, but that
-    // was not working, despite winit docs seeming to suggest that it should. But I found
-    // that I received no ReceivedCharacter events at all when typing backspace/enter.
-    //
-    // I'm guessing that the ReceivedCharacter approach would be ideal though if this
-    // solution doesn't work for people with non-english keyboards or dvorak layouts or
-    // whatever.
-
-    for ev in keyboard_input_events.iter() {
-        if ev.state.is_pressed() {
-            let maybe_char = match ev.key_code {
-                Some(KeyCode::A) => Some('a'),
-                Some(KeyCode::B) => Some('b'),
-                Some(KeyCode::C) => Some('c'),
-                Some(KeyCode::D) => Some('d'),
-                Some(KeyCode::E) => Some('e'),
-                Some(KeyCode::F) => Some('f'),
-                Some(KeyCode::G) => Some('g'),
-                Some(KeyCode::H) => Some('h'),
-                Some(KeyCode::I) => Some('i'),
-                Some(KeyCode::J) => Some('j'),
-                Some(KeyCode::K) => Some('k'),
-                Some(KeyCode::L) => Some('l'),
-                Some(KeyCode::M) => Some('m'),
-                Some(KeyCode::N) => Some('n'),
-                Some(KeyCode::O) => Some('o'),
-                Some(KeyCode::P) => Some('p'),
-                Some(KeyCode::Q) => Some('q'),
-                Some(KeyCode::R) => Some('r'),
-                Some(KeyCode::S) => Some('s'),
-                Some(KeyCode::T) => Some('t'),
-                Some(KeyCode::U) => Some('u'),
-                Some(KeyCode::V) => Some('v'),
-                Some(KeyCode::W) => Some('w'),
-                Some(KeyCode::X) => Some('x'),
-                Some(KeyCode::Y) => Some('y'),
-                Some(KeyCode::Z) => Some('z'),
-                Some(KeyCode::Minus) => Some('-'),
-                Some(KeyCode::Slash) => Some('?'), // should check for shift
-                Some(KeyCode::Key1) => Some('!'),  // should check for shift
-                _ => None,
-            };
+    // In kana mode, text committed by an IME also arrives as received characters, without
+    // key presses of their own. The winit version that bevy 0.5 uses doesn't give us the
+    // IME's composition (preedit) text, so the buffer only shows what has been committed.
 
-            if let Some(char) = maybe_char {
-                typing_state.buf.push(char);
-                typing_state.just_typed_char = true;
-            } else {
+    let mut chars: VecDeque<char> = received_character_events
+        .iter()
+        .map(|ev| ev.char)
+        .filter(|c| !c.is_control())
+        .collect();
+
+    let presses: Vec<&KeyboardInput> = keyboard_input_events
+        .iter()
+        .filter(|ev| ev.state.is_pressed())
+        .collect();
+
+    // How many of the remaining key presses will each take one of the received characters.
+    let mut character_presses = presses
+        .iter()
+        .filter(|ev| ev.key_code.map_or(false, is_character_key))
+        .count();
+
+    if *state.current() == TaipoState::Ready {
+        typing_stats.playing_seconds += time.delta_seconds_f64();
+    }
+    let now = typing_stats.playing_seconds;
+    let bindings = bindings_assets.get(&texture_handles.bindings);
+
+    for ev in presses {
+        if ev.key_code.map_or(false, is_character_key) {
+            character_presses -= 1;
+        }
+
+        let ctrl =
+            keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+        let bound_action = ev.key_code.and_then(|key| {
+            bindings.and_then(|b| b.key_action(key, ctrl, typing_state.buf.is_empty()))
+        });
+
+        match (bound_action, ev.key_code) {
+            (Some(Action::Submit), _) => {
+                typing_stats.keystrokes += 1;
+
+                // An IME commits its text with the same Return that submits it, so the
+                // characters that no key press will take need to be typed first.
+                while chars.len() > character_presses {
+                    let c = chars.pop_front().unwrap();
+                    push_char(&mut typing_state, &mut typing_stats, &targets, c, now);
+                }
+
+                submit(
+                    &mut typing_state,
+                    &mut typing_stats,
+                    &mut typing_submit_events,
+                    now,
+                );
+            }
+            (Some(Action::DeleteChar), _) => {
+                typing_stats.keystrokes += 1;
+                typing_stats.backspaces += 1;
+
+                typing_state.buf.pop();
                 typing_state.just_typed_char = false;
             }
+            (Some(Action::DeleteWord), _) => {
+                typing_stats.keystrokes += 1;
 
-            if ev.key_code == Some(KeyCode::Return) {
-                let text = typing_state.buf.clone();
+                let len = delete_word_len(&typing_state.buf, typing_state.input_mode, &targets);
+                typing_state.buf.truncate(len);
+                typing_state.just_typed_char = false;
+            }
+            (Some(Action::ClearLine), _) => {
+                typing_stats.keystrokes += 1;
 
                 typing_state.buf.clear();
-                typing_submit_events.send(TypingSubmitEvent { text });
+                typing_state.just_typed_char = false;
             }
-
-            if ev.key_code == Some(KeyCode::Back) {
-                typing_state.buf.pop();
+            (Some(action), _) => {
+                action_events.send(ActionEvent(action.clone()));
             }
+            (None, Some(key)) if is_character_key(key) => {
+                if let Some(c) = chars.pop_front() {
+                    push_char(&mut typing_state, &mut typing_stats, &targets, c, now);
+                    auto_submit(
+                        &mut typing_state,
+                        &mut typing_stats,
+                        &mut typing_submit_events,
+                        &targets,
+                        now,
+                    );
+                }
+            }
+            _ => {}
         }
     }
+
+    for c in chars {
+        push_char(&mut typing_state, &mut typing_stats, &targets, c, now);
+        auto_submit(
+            &mut typing_state,
+            &mut typing_stats,
+            &mut typing_submit_events,
+            &targets,
+            now,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(render: &[&str], ascii: &[&str]) -> TypingTarget {
+        TypingTarget {
+            render: render.iter().map(|r| r.to_string()).collect(),
+            ascii: ascii.iter().map(|a| vec![a.to_string()]).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn help() -> TypingTarget {
+        target(&["h", "e", "l", "p"], &["h", "e", "l", "p"])
+    }
+
+    #[test]
+    fn reserved_in_romaji() {
+        let mut typing_targets = TypingTargets::default();
+        typing_targets.set_possible(vec![target(&["へ"], &["he"])], InputMode::Romaji);
+        typing_targets.reserve(&help());
+
+        assert_eq!(typing_targets.pick(is_prefix_ambiguous), None);
+    }
+
+    #[test]
+    fn reserved_in_kana() {
+        // `help` is typed as is, which doesn't get in the way of typing へ.
+        let mut typing_targets = TypingTargets::default();
+        typing_targets.set_possible(vec![target(&["へ"], &["he"])], InputMode::Kana);
+        typing_targets.reserve(&help());
+
+        assert_eq!(typing_targets.pick(is_prefix_ambiguous), Some(0));
+    }
+
+    #[test]
+    fn reserved_target_is_picked_last() {
+        let mut typing_targets = TypingTargets::default();
+        typing_targets.set_possible(
+            vec![target(&["へ"], &["he"]), target(&["か"], &["ka"])],
+            InputMode::Romaji,
+        );
+        typing_targets.reserve(&help());
+
+        assert_eq!(typing_targets.pop_front().render, vec!["か"]);
+        assert_eq!(typing_targets.pop_front().render, vec!["へ"]);
+    }
 }